@@ -1,6 +1,161 @@
 // IMMEDIATELY override console before ANY other code runs
 (function () {
-  const methods = ["log", "error", "warn", "info"];
+  // The IDE's origins when the preview is isolated on its own origin,
+  // otherwise it shares ours. Never "*": any page could frame the preview.
+  const targets = document.currentScript?.dataset.ideOrigins?.split(" ") ?? [
+    location.origin,
+  ];
+  const methods = ["log", "error", "warn", "info", "debug"];
+
+  // Entries also go over the WebSocket (hmr-client.js sends them), so the IDE
+  // sees them when the page is open in its own tab or on another device. The
+  // framing IDE gets both copies and drops the second by id.
+  const pageId = Math.random().toString(36).slice(2, 10);
+  let seq = 0;
+  const MAX_QUEUED = 200;
+  const relay = (globalThis.__wss_console = {
+    /** Entries logged before the socket is up */
+    queue: [],
+    /** Set by hmr-client.js once connected */
+    send: null,
+    /** Also used for `eval::result` values */
+    serialize: (value) => serialize(value, 0, new WeakSet()),
+  });
+
+  function emit(level, args, stack) {
+    const entry = {
+      id: `${pageId}:${seq++}`,
+      level,
+      args: args.map((arg) => serialize(arg, 0, new WeakSet())),
+      stack,
+      url: location.href,
+      time: Date.now(),
+    };
+
+    // Send to parent console
+    if (window.parent !== window) {
+      const message = { source: "iframe-console", type: level, entry };
+      for (const target of targets) window.parent.postMessage(message, target);
+    }
+
+    if (relay.send) {
+      relay.send(entry);
+    } else {
+      relay.queue.push(entry);
+      if (relay.queue.length > MAX_QUEUED) relay.queue.shift();
+    }
+  }
+
+  const MAX_DEPTH = 4;
+  const MAX_ITEMS = 100;
+  const MAX_STRING = 10000;
+
+  /**
+   * Plain msgpack/structured-clone safe copy of a logged value. What can't be
+   * represented (functions, DOM nodes, cycles, ...) becomes `{ $type, ... }`.
+   */
+  function serialize(value, depth, seen) {
+    switch (typeof value) {
+      case "string":
+        return value.length > MAX_STRING
+          ? `${value.slice(0, MAX_STRING)}… (${value.length} chars)`
+          : value;
+      case "boolean":
+        return value;
+      case "number":
+        return Number.isFinite(value) && !Object.is(value, -0)
+          ? value
+          : { $type: "number", value: Object.is(value, -0) ? "-0" : String(value) };
+      case "undefined":
+        return { $type: "undefined" };
+      case "bigint":
+        return { $type: "bigint", value: value.toString() };
+      case "symbol":
+        return { $type: "symbol", value: value.toString() };
+      case "function":
+        return { $type: "function", name: value.name || "anonymous" };
+    }
+    if (value === null) return null;
+
+    try {
+      if (value instanceof Error) {
+        return {
+          $type: "error",
+          name: value.name,
+          message: String(value.message),
+          stack: String(value.stack ?? ""),
+        };
+      }
+      if (value instanceof Date) return { $type: "date", value: value.getTime() };
+      if (value instanceof RegExp) return { $type: "regexp", value: String(value) };
+      if (typeof Node !== "undefined" && value instanceof Node) {
+        return { $type: "node", value: describeNode(value) };
+      }
+      if (ArrayBuffer.isView(value) || value instanceof ArrayBuffer) {
+        return {
+          $type: "binary",
+          name: value.constructor.name,
+          length: value.byteLength,
+        };
+      }
+      if (value instanceof Promise) return { $type: "promise" };
+      if (value instanceof WeakMap || value instanceof WeakSet) {
+        return { $type: "opaque", name: value.constructor.name };
+      }
+
+      if (seen.has(value)) return { $type: "circular" };
+      if (depth >= MAX_DEPTH) {
+        return { $type: "truncated", name: value.constructor?.name ?? "Object" };
+      }
+      seen.add(value);
+      const inner = (item) => serialize(item, depth + 1, seen);
+
+      let out;
+      if (value instanceof Map) {
+        out = {
+          $type: "map",
+          entries: [...value].slice(0, MAX_ITEMS).map(([k, v]) => [inner(k), inner(v)]),
+          size: value.size,
+        };
+      } else if (value instanceof Set) {
+        out = { $type: "set", values: [...value].slice(0, MAX_ITEMS).map(inner), size: value.size };
+      } else if (Array.isArray(value)) {
+        out = value.slice(0, MAX_ITEMS).map(inner);
+        if (value.length > MAX_ITEMS) out.push({ $type: "more", count: value.length - MAX_ITEMS });
+      } else {
+        out = {};
+        const keys = Object.keys(value);
+        for (const key of keys.slice(0, MAX_ITEMS)) {
+          let item;
+          try {
+            item = value[key];
+          } catch (err) {
+            // Throwing getter
+            item = err;
+          }
+          out[key] = inner(item);
+        }
+        if (keys.length > MAX_ITEMS) {
+          out["…"] = { $type: "more", count: keys.length - MAX_ITEMS };
+        }
+      }
+      seen.delete(value);
+      return out;
+    } catch {
+      // Proxies and exotic objects can throw on any access
+      return { $type: "opaque", name: "Object" };
+    }
+  }
+
+  function describeNode(node) {
+    if (node.nodeType === Node.ELEMENT_NODE) {
+      const id = node.id ? `#${node.id}` : "";
+      const classes = [...node.classList].map((c) => `.${c}`).join("");
+      return `<${node.localName}${id}${classes}>`;
+    }
+    return node.nodeName;
+  }
+
   methods.forEach((method) => {
     const original = console[method];
     console[method] = function (...args) {
@@ -8,17 +163,24 @@
 
       // Capture stack trace for source location
       const stackLine = new Error().stack?.split("\n")[2]?.trim() || "";
-
-      // Send to parent console
-      window.parent.postMessage(
-        {
-          source: "iframe-console",
-          type: method,
-          args: args,
-          stack: stackLine,
-        },
-        "*",
-      );
+      emit(method, args, stackLine);
     };
   });
+
+  const originalClear = console.clear;
+  console.clear = function () {
+    originalClear.call(console);
+    emit("clear", [], "");
+  };
+
+  window.addEventListener("error", (event) => {
+    // Resource load failures bubble here too, without an error object
+    if (!(event instanceof ErrorEvent)) return;
+    const where = event.filename ? `${event.filename}:${event.lineno}:${event.colno}` : "";
+    emit("exception", [event.error ?? event.message], event.error?.stack ?? where);
+  });
+
+  window.addEventListener("unhandledrejection", (event) => {
+    emit("rejection", [event.reason], event.reason?.stack ?? "");
+  });
 })();
@@ -1,7 +1,70 @@
-import { decodeMulti } from "https://cdn.jsdelivr.net/npm/@msgpack/msgpack@3.1.3/dist.esm/index.mjs";
+import { decodeMulti, encode } from "/__wss/vendor/msgpack.js";
 
 globalThis.__hmr_cache = new Map();
 
+class DiagnosticsOverlay {
+  constructor() {
+    /** @type {Map<string, Array<object>>} diagnostics per file */
+    this.files = new Map();
+    this.el = null;
+  }
+
+  show(diagnostics) {
+    if (!diagnostics.length) return;
+    this.files.set(diagnostics[0].file, diagnostics);
+    this.render();
+  }
+
+  clear(file) {
+    this.files.delete(file);
+    this.render();
+  }
+
+  render() {
+    if (!this.files.size) {
+      this.el?.remove();
+      this.el = null;
+      return;
+    }
+
+    if (!this.el) {
+      this.el = document.createElement("div");
+      this.el.id = "__wss-diagnostics-overlay";
+      this.el.style.cssText = `
+        position: fixed; inset: 0; z-index: 2147483647; overflow: auto;
+        padding: 24px; background: rgba(20, 20, 20, 0.92); color: #eee;
+        font: 13px/1.5 monospace; white-space: pre-wrap;
+      `;
+      this.el.addEventListener("click", (e) => {
+        if (e.target === this.el) this.el.style.display = "none";
+      });
+    }
+    (document.body ?? document.documentElement).appendChild(this.el);
+    this.el.style.display = "";
+
+    this.el.replaceChildren();
+    for (const diagnostics of this.files.values()) {
+      for (const d of diagnostics) {
+        const title = document.createElement("div");
+        title.style.cssText = "color: #f48771; font-weight: bold;";
+        title.textContent = `${d.file}:${d.line}:${d.column}  ${d.message}`;
+
+        const frame = document.createElement("pre");
+        frame.style.cssText =
+          "margin: 8px 0 20px; padding: 8px; background: #1e1e1e; color: #d4d4d4;";
+        frame.textContent = d.code_frame;
+
+        this.el.append(title, frame);
+      }
+    }
+  }
+}
+
+const diagnosticsOverlay = new DiagnosticsOverlay();
+// Called by the error modules the server serves for files that fail to transpile
+globalThis.__wss_diagnostics_overlay = (diagnostics) =>
+  diagnosticsOverlay.show(diagnostics);
+
 class HMRClient {
   constructor() {
     window.__hmr_cache = window.__hmr_cache || new Map();
@@ -9,15 +72,27 @@ class HMRClient {
     this.reconnectAttempts = 0;
     this.maxReconnectAttempts = 10;
     this.reconnectDelay = 1000; // ms, grows exponentially
+    /** @type {Map<string, Set<Function>>} pub/sub handlers by pattern */
+    this.subscriptions = new Map();
+
+    // Pub/sub for project code, e.g. to coordinate with IDE components
+    globalThis.__wss_pubsub = {
+      subscribe: (pattern, handler) => this.subscribe(pattern, handler),
+      publish: (topic, body, options) => this.publish(topic, body, options),
+    };
 
     this.connect();
   }
 
   connect() {
-    this.ws = new WebSocket(`ws://${location.host}/ws/`);
+    this.ws = new WebSocket(`ws://${location.host}/ws/?role=preview`);
     this.ws.binaryType = "arraybuffer";
 
     this.ws.onopen = () => {
+      // Lets the IDE find (and evaluate code in) this page
+      this.ws.send(encode({ type: "client::hello", body: "", msg_id: "", url: location.href }));
+      this.relayConsole();
+      for (const topic of this.subscriptions.keys()) this.sendMessage("subscribe", { topic });
       console.log("🔗 HMR connected");
       this.reconnectAttempts = 0;
       this.reconnectDelay = 1000;
@@ -31,7 +106,55 @@ class HMRClient {
     };
   }
 
+  sendMessage(type, fields) {
+    if (this.ws.readyState !== WebSocket.OPEN) return false;
+    this.ws.send(encode({ type, body: "", msg_id: "", ...fields }));
+    return true;
+  }
+
+  /** Returns a function that removes the handler again. */
+  subscribe(pattern, handler) {
+    let handlers = this.subscriptions.get(pattern);
+    if (!handlers) {
+      handlers = new Set();
+      this.subscriptions.set(pattern, handlers);
+      // Otherwise sent once connected
+      this.sendMessage("subscribe", { topic: pattern });
+    }
+    handlers.add(handler);
+    return () => {
+      handlers.delete(handler);
+      if (handlers.size || this.subscriptions.get(pattern) !== handlers) return;
+      this.subscriptions.delete(pattern);
+      this.sendMessage("unsubscribe", { topic: pattern });
+    };
+  }
+
+  /** `false` if not connected (the message is dropped). */
+  publish(topic, body, { retain = false } = {}) {
+    return this.sendMessage("publish", { topic, body, retain });
+  }
+
+  /** Sends console-override.js entries (queued ones first) as `console::*`. */
+  relayConsole() {
+    const relay = globalThis.__wss_console;
+    if (!relay) return;
+    const ws = this.ws;
+    relay.send = (entry) => {
+      if (ws.readyState !== WebSocket.OPEN) {
+        relay.send = null;
+        relay.queue.push(entry);
+        return;
+      }
+      ws.send(
+        encode({ type: `console::${entry.level}`, body: "", msg_id: entry.id, entry }),
+      );
+    };
+    for (const entry of relay.queue.splice(0)) relay.send(entry);
+  }
+
   handleDisconnect() {
+    if (globalThis.__wss_console) globalThis.__wss_console.send = null;
     console.warn("🔌 HMR disconnected, reconnecting...");
 
     if (this.reconnectAttempts < this.maxReconnectAttempts) {
@@ -52,11 +175,52 @@ class HMRClient {
   }
 
   handleMsgpack(arrayBuffer) {
-    const messages = decodeMulti(new Uint8Array(arrayBuffer));
-    messages.forEach((msg) => this.handleHmrEvent(msg));
+    for (const msg of decodeMulti(new Uint8Array(arrayBuffer))) {
+      this.handleHmrEvent(msg);
+    }
+  }
+
+  /** Runs an IDE `eval::request` in the page's global scope. */
+  async evaluate(msg) {
+    const serialize = globalThis.__wss_console?.serialize ?? ((value) => String(value));
+    let reply;
+    try {
+      // Indirect eval: global scope, like a devtools console
+      const value = await (0, eval)(msg.body);
+      reply = { ok: true, value: serialize(value) };
+    } catch (err) {
+      reply = { ok: false, error: serialize(err) };
+    }
+    this.ws.send(
+      encode({ type: "eval::result", body: "", msg_id: msg.msg_id, ...reply }),
+    );
   }
 
   handleHmrEvent(msg) {
+    if (msg.type === "eval::request") {
+      this.evaluate(msg);
+      return;
+    }
+    if (msg.type === "message") {
+      for (const [pattern, handlers] of this.subscriptions) {
+        if (!topicMatches(pattern, msg.topic)) continue;
+        for (const handler of handlers) handler(msg.body, msg);
+      }
+      return;
+    }
+    if (/^(un)?subscribe$|^publish$/.test(msg.type)) {
+      if (msg.error) console.warn("⚠️ pub/sub:", msg.error);
+      return;
+    }
+    if (msg.type === "diagnostics::error") {
+      diagnosticsOverlay.show(msg.diagnostics);
+      return;
+    }
+    if (msg.type === "diagnostics::clear") {
+      diagnosticsOverlay.clear(msg.body);
+      return;
+    }
+
     // Debounce
     const key = `${msg.type}:${msg.body}`;
     if (msg.action === "remove" || !msg.body.startsWith("/project/")) return;
@@ -70,7 +234,7 @@ class HMRClient {
     setTimeout(() => {
       switch (msg.type) {
         case "hmr::css_update":
-          this.reloadCSS(msg.body);
+          this.reloadCSS(msg.body, msg.dependents);
           break;
         case "hmr::js_update":
           this.reloadJS(msg.body);
@@ -79,10 +243,29 @@ class HMRClient {
     }, 10);
   }
 
-  reloadCSS(path) {
-    document.querySelectorAll('link[href*=".css"]').forEach((link) => {
-      link.href += `?t=${Date.now()}`;
+  reloadCSS(path, dependents = [path]) {
+    const affected = new Set(dependents);
+    let refreshed = 0;
+
+    document.querySelectorAll('link[rel="stylesheet"]').forEach((link) => {
+      const url = new URL(link.href, location.href);
+      if (url.origin !== location.origin || !affected.has(url.pathname)) return;
+      url.searchParams.set("t", Date.now());
+      link.href = url.href;
+      refreshed++;
+    });
+
+    // Stylesheets imported from JS: re-importing the module swaps its <style>
+    document.querySelectorAll("style[data-wss-css]").forEach((style) => {
+      const id = style.dataset.wssCss;
+      if (!affected.has(id)) return;
+      import(`${id}?import&t=${Date.now()}`).catch((err) =>
+        console.warn("⚠️ HMR CSS update failed:", id, err),
+      );
+      refreshed++;
     });
+
+    if (refreshed === 0) console.log("🎨 HMR CSS: no matching stylesheet for", path);
   }
 
   reloadJS(path) {
@@ -114,4 +297,15 @@ class HMRClient {
   }
 }
 
+/** Whether subscription `pattern` covers `topic` (mirrors `pubsub::matches`). */
+function topicMatches(pattern, topic) {
+  const want = pattern.split("/");
+  const have = topic.split("/");
+  for (let i = 0; i < want.length; i++) {
+    if (want[i] === "**") return true;
+    if (i >= have.length || (want[i] !== "*" && want[i] !== have[i])) return false;
+  }
+  return want.length === have.length;
+}
+
 new HMRClient();
@@ -0,0 +1,720 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{
+    ArrowFunctionExpression, AwaitExpression, Declaration, ExportDefaultDeclarationKind,
+    ExportNamedDeclaration, Expression, ForOfStatement, Function, IdentifierReference,
+    ImportDeclarationSpecifier, ImportExpression, ObjectProperty, Statement,
+};
+use oxc_ast_visit::{walk, Visit};
+use oxc_codegen::{Codegen, CodegenOptions};
+use oxc_parser::Parser;
+use oxc_semantic::ScopeFlags;
+use oxc_semantic::{Scoping, SemanticBuilder, SymbolId};
+use oxc_span::{GetSpan, SourceType, Span};
+
+use crate::http::assets::{self, ImportKind};
+use crate::http::css;
+use crate::http::deps;
+use crate::http::resolve::{self, quote};
+use crate::http::routes::project_root;
+use crate::http::transform_options::{JsxMode, TransformSettings};
+use crate::http::transpile::transpile_ts_to_js;
+
+/// Tried in order for extensionless (and `.js`-for-`.ts`) imports.
+const PROBE_SUFFIXES: &[&str] = &[
+    "",
+    ".ts",
+    ".tsx",
+    ".mts",
+    ".js",
+    ".jsx",
+    ".mjs",
+    ".cjs",
+    ".json",
+    "/index.ts",
+    "/index.tsx",
+    "/index.js",
+    "/index.mjs",
+];
+
+/// Runtime shared by every bundle: a module registry keyed by numeric id.
+/// Exports are getters on a plain object, so bindings stay live across cycles.
+const RUNTIME: &str = r#"globalThis.process ??= { env: { NODE_ENV: "production" } };
+const __wss_cache = {};
+function __wss_require(id) {
+  if (id in __wss_cache) return __wss_cache[id];
+  const exports = (__wss_cache[id] = Object.create(null));
+  Object.defineProperty(exports, Symbol.toStringTag, { value: "Module" });
+  __wss_modules[id](exports);
+  return exports;
+}
+function __wss_export(exports, getters) {
+  for (const name in getters) Object.defineProperty(exports, name, { get: getters[name], enumerable: true });
+}
+function __wss_export_star(exports, ns) {
+  for (const name in ns) {
+    if (name !== "default" && !(name in exports)) Object.defineProperty(exports, name, { get: () => ns[name], enumerable: true });
+  }
+}
+"#;
+
+/// Words a mangled name must not be.
+const RESERVED: &[&str] = &[
+    "do",
+    "if",
+    "in",
+    "for",
+    "let",
+    "new",
+    "try",
+    "var",
+    "case",
+    "else",
+    "enum",
+    "eval",
+    "null",
+    "this",
+    "true",
+    "void",
+    "with",
+    "await",
+    "break",
+    "catch",
+    "class",
+    "const",
+    "false",
+    "super",
+    "throw",
+    "while",
+    "yield",
+    "delete",
+    "export",
+    "import",
+    "public",
+    "return",
+    "static",
+    "switch",
+    "typeof",
+    "default",
+    "extends",
+    "finally",
+    "package",
+    "private",
+    "continue",
+    "debugger",
+    "function",
+    "arguments",
+    "interface",
+    "protected",
+    "implements",
+    "instanceof",
+    "undefined",
+    "NaN",
+    "Infinity",
+];
+
+/// Bundles `entry` and everything it statically or (literally) dynamically
+/// imports into one ES module, with whitespace and comments stripped and
+/// local names shortened (no dead-code removal or constant folding). Remote
+/// URLs stay as real imports.
+pub fn bundle(entry: &Path) -> Result<String, String> {
+    bundle_source(&load(entry)?, entry)
+}
+
+/// `bundle` for module code that has no file of its own, like an inline
+/// `<script type="module">`; its imports resolve from `path`.
+pub fn bundle_source(code: &str, path: &Path) -> Result<String, String> {
+    let mut bundler = Bundler::default();
+    bundler.id_of(path);
+    let mut entry = Some(code.to_string());
+
+    while let Some((id, path)) = bundler.queue.pop_front() {
+        let code = match entry.take() {
+            Some(code) => code,
+            None => load(&path)?,
+        };
+        let wrapped = bundler.wrap_module(&code, &path)?;
+        bundler.modules.push((id, wrapped));
+    }
+    bundler.modules.sort_by_key(|(id, _)| *id);
+
+    let mut out = String::new();
+    for (i, url) in bundler.externals.iter().enumerate() {
+        out.push_str(&format!(
+            "import * as __wss_ext{} from {};\n",
+            i,
+            quote(url)
+        ));
+    }
+    out.push_str(RUNTIME);
+    out.push_str("const __wss_modules = {\n");
+    for (id, code) in &bundler.modules {
+        out.push_str(&format!("{}: function (exports) {{\n{}\n}},\n", id, code));
+    }
+    out.push_str("};\n__wss_require(0);\n");
+
+    compact(&out, path)
+}
+
+#[derive(Default)]
+struct Bundler {
+    ids: HashMap<PathBuf, usize>,
+    queue: VecDeque<(usize, PathBuf)>,
+    modules: Vec<(usize, String)>,
+    externals: Vec<String>,
+}
+
+/// What an import specifier points at.
+enum Target {
+    Module(usize),
+    External(usize),
+}
+
+impl Bundler {
+    fn id_of(&mut self, path: &Path) -> usize {
+        if let Some(id) = self.ids.get(path) {
+            return *id;
+        }
+        let id = self.ids.len();
+        self.ids.insert(path.to_path_buf(), id);
+        self.queue.push_back((id, path.to_path_buf()));
+        id
+    }
+
+    fn target(&mut self, specifier: &str, importer: &Path) -> Result<Target, String> {
+        if is_external(specifier) {
+            let index = match self.externals.iter().position(|url| url == specifier) {
+                Some(index) => index,
+                None => {
+                    self.externals.push(specifier.to_string());
+                    self.externals.len() - 1
+                }
+            };
+            return Ok(Target::External(index));
+        }
+        // `?raw` / `?url` imports are separate modules from the file itself
+        let (path, query) = specifier.split_once('?').unwrap_or((specifier, ""));
+        match resolve_specifier(path, importer) {
+            Some(file) => {
+                let flag = query
+                    .split('&')
+                    .find(|flag| *flag == "raw" || *flag == "url");
+                let key = match flag {
+                    Some(flag) => PathBuf::from(format!("{}?{}", file.display(), flag)),
+                    None => file,
+                };
+                Ok(Target::Module(self.id_of(&key)))
+            }
+            None => Err(format!(
+                "Cannot resolve \"{}\" from {}",
+                specifier,
+                importer.display()
+            )),
+        }
+    }
+
+    /// Namespace expression for a static import.
+    fn namespace(&mut self, specifier: &str, importer: &Path) -> Result<String, String> {
+        Ok(match self.target(specifier, importer)? {
+            Target::Module(id) => format!("__wss_require({})", id),
+            Target::External(index) => format!("__wss_ext{}", index),
+        })
+    }
+
+    /// Rewrites one ES module into the body of a registry function: imports
+    /// become `__wss_require` calls (references read through the namespace, so
+    /// they stay live), exports become getters on `exports`.
+    fn wrap_module(&mut self, code: &str, path: &Path) -> Result<String, String> {
+        let allocator = Allocator::default();
+        let ret = Parser::new(&allocator, code, SourceType::mjs()).parse();
+        if let Some(error) = ret.errors.first() {
+            return Err(format!("{}: {}", path.display(), error));
+        }
+        // The registry calls module functions synchronously
+        let mut awaits = TopLevelAwait::default();
+        awaits.visit_program(&ret.program);
+        if awaits.found {
+            return Err(format!(
+                "{}: top-level await can't be bundled; move it into an async function",
+                path.display()
+            ));
+        }
+        let semantic = SemanticBuilder::new().build(&ret.program).semantic;
+
+        let mut edits: Vec<(Span, String)> = Vec::new();
+        let mut getters: Vec<(String, String)> = Vec::new();
+        let mut header = String::new();
+        // Imported binding -> expression that reads it
+        let mut imports: HashMap<SymbolId, String> = HashMap::new();
+        let mut imported_names: HashMap<String, String> = HashMap::new();
+        let mut namespaces = 0;
+        let mut bind = |header: &mut String, namespace: String| {
+            let local = format!("__wss_m{}", namespaces);
+            namespaces += 1;
+            header.push_str(&format!("const {} = {};\n", local, namespace));
+            local
+        };
+
+        for statement in &ret.program.body {
+            match statement {
+                Statement::ImportDeclaration(import) => {
+                    let ns = self.namespace(&import.source.value, path)?;
+                    let ns = bind(&mut header, ns);
+                    for specifier in import.specifiers.iter().flatten() {
+                        let (local, value) = match specifier {
+                            ImportDeclarationSpecifier::ImportSpecifier(s) => {
+                                (&s.local, format!("{}[{}]", ns, quote(&s.imported.name())))
+                            }
+                            ImportDeclarationSpecifier::ImportDefaultSpecifier(s) => {
+                                (&s.local, format!("{}.default", ns))
+                            }
+                            ImportDeclarationSpecifier::ImportNamespaceSpecifier(s) => {
+                                (&s.local, ns.clone())
+                            }
+                        };
+                        if let Some(symbol) = local.symbol_id.get() {
+                            imports.insert(symbol, value.clone());
+                        }
+                        imported_names.insert(local.name.to_string(), value);
+                    }
+                    edits.push((import.span, String::new()));
+                }
+                Statement::ExportNamedDeclaration(export) => {
+                    if let Some(declaration) = &export.declaration {
+                        for name in declared_names(declaration) {
+                            getters.push((name.clone(), name));
+                        }
+                        edits.push((
+                            Span::new(export.span.start, declaration.span().start),
+                            String::new(),
+                        ));
+                    } else {
+                        let ns = match &export.source {
+                            Some(source) => {
+                                let ns = self.namespace(&source.value, path)?;
+                                Some(bind(&mut header, ns))
+                            }
+                            None => None,
+                        };
+                        for specifier in &export.specifiers {
+                            let local = specifier.local.name();
+                            let value = match &ns {
+                                Some(ns) => format!("{}[{}]", ns, quote(&local)),
+                                None => imported_names
+                                    .get(local.as_ref())
+                                    .cloned()
+                                    .unwrap_or_else(|| local.to_string()),
+                            };
+                            getters.push((specifier.exported.name().to_string(), value));
+                        }
+                        edits.push((export.span, String::new()));
+                    }
+                }
+                Statement::ExportDefaultDeclaration(export) => {
+                    let declaration = &export.declaration;
+                    let named = match declaration {
+                        ExportDefaultDeclarationKind::FunctionDeclaration(f) => {
+                            f.id.as_ref().map(|id| id.name.to_string())
+                        }
+                        ExportDefaultDeclarationKind::ClassDeclaration(c) => {
+                            c.id.as_ref().map(|id| id.name.to_string())
+                        }
+                        _ => None,
+                    };
+                    let keyword = Span::new(export.span.start, declaration.span().start);
+                    match named {
+                        Some(name) => {
+                            edits.push((keyword, String::new()));
+                            getters.push(("default".to_string(), name));
+                        }
+                        None => {
+                            edits.push((keyword, "const __wss_default = ".to_string()));
+                            let end = declaration.span().end;
+                            edits.push((Span::new(end, end), ";".to_string()));
+                            getters.push(("default".to_string(), "__wss_default".to_string()));
+                        }
+                    }
+                }
+                Statement::ExportAllDeclaration(export) => {
+                    let ns = self.namespace(&export.source.value, path)?;
+                    let ns = bind(&mut header, ns);
+                    match &export.exported {
+                        Some(name) => getters.push((name.name().to_string(), ns)),
+                        None => header.push_str(&format!("__wss_export_star(exports, {});\n", ns)),
+                    }
+                    edits.push((export.span, String::new()));
+                }
+                _ => {}
+            }
+        }
+
+        let mut refs = ModuleRefs {
+            scoping: semantic.scoping(),
+            imports: &imports,
+            edits: Vec::new(),
+            dynamic: Vec::new(),
+        };
+        refs.visit_program(&ret.program);
+        edits.extend(refs.edits);
+
+        // `import("./x")` stays lazy but resolves from the registry
+        for (span, specifier) in refs.dynamic {
+            if is_external(&specifier) {
+                continue;
+            }
+            if let Target::Module(id) = self.target(&specifier, path)? {
+                edits.push((
+                    span,
+                    format!("Promise.resolve().then(() => __wss_require({}))", id),
+                ));
+            }
+        }
+
+        let mut body = code.to_string();
+        edits.sort_by_key(|(span, _)| std::cmp::Reverse((span.start, span.end)));
+        for (span, replacement) in edits {
+            body.replace_range(span.start as usize..span.end as usize, &replacement);
+        }
+
+        let getters: Vec<String> = getters
+            .iter()
+            .map(|(name, value)| format!("{}: () => {}", quote(name), value))
+            .collect();
+        Ok(format!(
+            "__wss_export(exports, {{ {} }});\n{}{}",
+            getters.join(", "),
+            header,
+            body
+        ))
+    }
+}
+
+/// Finds references to imported bindings and literal dynamic imports.
+struct ModuleRefs<'s> {
+    scoping: &'s Scoping,
+    imports: &'s HashMap<SymbolId, String>,
+    edits: Vec<(Span, String)>,
+    dynamic: Vec<(Span, String)>,
+}
+
+impl ModuleRefs<'_> {
+    fn import_of(&self, ident: &IdentifierReference) -> Option<&String> {
+        let reference = self.scoping.get_reference(ident.reference_id.get()?);
+        self.imports.get(&reference.symbol_id()?)
+    }
+}
+
+impl<'a> Visit<'a> for ModuleRefs<'_> {
+    fn visit_identifier_reference(&mut self, it: &IdentifierReference<'a>) {
+        if let Some(value) = self.import_of(it) {
+            self.edits.push((it.span, value.clone()));
+        }
+    }
+
+    fn visit_object_property(&mut self, it: &ObjectProperty<'a>) {
+        // `{ x }` -> `{ x: ns.x }`
+        if let (true, Expression::Identifier(ident)) = (it.shorthand, &it.value) {
+            if let Some(value) = self.import_of(ident) {
+                self.edits
+                    .push((it.span, format!("{}: {}", ident.name, value)));
+                return;
+            }
+        }
+        walk::walk_object_property(self, it);
+    }
+
+    fn visit_export_named_declaration(&mut self, it: &ExportNamedDeclaration<'a>) {
+        // Specifier lists are removed wholesale and turned into getters
+        if let Some(declaration) = &it.declaration {
+            self.visit_declaration(declaration);
+        }
+    }
+
+    fn visit_import_expression(&mut self, it: &ImportExpression<'a>) {
+        if let Expression::StringLiteral(source) = &it.source {
+            self.dynamic.push((it.span, source.value.to_string()));
+        }
+        walk::walk_import_expression(self, it);
+    }
+}
+
+/// Whether a module awaits outside of any function.
+#[derive(Default)]
+struct TopLevelAwait {
+    found: bool,
+}
+
+impl<'a> Visit<'a> for TopLevelAwait {
+    fn visit_function(&mut self, _: &Function<'a>, _: ScopeFlags) {}
+
+    fn visit_arrow_function_expression(&mut self, _: &ArrowFunctionExpression<'a>) {}
+
+    fn visit_await_expression(&mut self, _: &AwaitExpression<'a>) {
+        self.found = true;
+    }
+
+    fn visit_for_of_statement(&mut self, it: &ForOfStatement<'a>) {
+        self.found |= it.r#await;
+        walk::walk_for_of_statement(self, it);
+    }
+}
+
+fn declared_names(declaration: &Declaration) -> Vec<String> {
+    match declaration {
+        Declaration::VariableDeclaration(variables) => variables
+            .declarations
+            .iter()
+            .flat_map(|d| d.id.get_binding_identifiers())
+            .map(|id| id.name.to_string())
+            .collect(),
+        other => other
+            .id()
+            .map(|id| id.name.to_string())
+            .into_iter()
+            .collect(),
+    }
+}
+
+/// Source of one module as plain ES module JS.
+fn load(path: &Path) -> Result<String, String> {
+    let key = path.to_string_lossy();
+    let path = Path::new(key.split('?').next().unwrap_or(&key));
+    match assets::import_kind(&key) {
+        Some(ImportKind::Raw) => {
+            let text = fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            return Ok(format!(
+                "export default {};",
+                quote(&String::from_utf8_lossy(&text))
+            ));
+        }
+        // Assets are copied as-is, so root-relative URLs stay valid in dist/
+        Some(ImportKind::Url) => {
+            let relative = path.strip_prefix(project_root()).unwrap_or(path);
+            return Ok(format!(
+                "export default {};",
+                quote(&format!("/{}", relative.display()))
+            ));
+        }
+        _ => {}
+    }
+
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+    match ext {
+        "ts" | "tsx" | "jsx" | "mts" => {
+            let transpiled = transpile_ts_to_js(&source, path, &build_settings(path));
+            match transpiled.diagnostics.first() {
+                Some(d) => Err(format!("{}:{}:{}: {}", d.file, d.line, d.column, d.message)),
+                None => Ok(transpiled.code),
+            }
+        }
+        "js" | "mjs" | "cjs" => {
+            if deps::is_dependency(path) && deps::is_commonjs(&source, path) {
+                Ok(deps::to_esm(&source, path))
+            } else {
+                Ok(source)
+            }
+        }
+        "json" => Ok(format!("export default {};", source.trim())),
+        "css" => {
+            let processed = css::process(path, true, &mut |file| {
+                let relative = file.strip_prefix(project_root()).ok()?;
+                Some(format!("/{}", relative.display()))
+            });
+            match processed.diagnostics.first() {
+                Some(d) => Err(format!("{}:{}:{}: {}", d.file, d.line, d.column, d.message)),
+                None => Ok(css::js_module(&processed, path)),
+            }
+        }
+        _ => Err(format!("{}: unsupported module type", path.display())),
+    }
+}
+
+/// Dev settings for the file, minus dev-only JSX (`jsxDEV` + source info).
+fn build_settings(path: &Path) -> TransformSettings {
+    let mut settings = TransformSettings::for_file(path);
+    if settings.jsx == JsxMode::AutomaticDev {
+        settings.jsx = JsxMode::Automatic;
+    }
+    settings
+}
+
+/// Like the dev server's resolution, plus TS extensions and `/project` URLs
+/// (as written by import rewriting and the CommonJS converter).
+pub fn resolve_specifier(specifier: &str, importer: &Path) -> Option<PathBuf> {
+    let root = project_root();
+    let candidate = if let Some(rest) = specifier.strip_prefix("/project/") {
+        resolve::module_file(rest).unwrap_or_else(|| root.join(rest))
+    } else if let Some(rest) = specifier.strip_prefix('/') {
+        root.join(rest)
+    } else if specifier.starts_with('.') {
+        importer.parent()?.join(specifier)
+    } else {
+        return resolve::resolve_node_module(specifier, importer).or_else(|| {
+            let url = resolve::resolve_bare(specifier, importer)?;
+            (!resolve::is_bare(&url) && !is_external(&url))
+                .then(|| resolve_specifier(&url, importer))
+                .flatten()
+        });
+    };
+    probe(&resolve::normalize(&candidate))
+}
+
+fn probe(path: &Path) -> Option<PathBuf> {
+    let found = PROBE_SUFFIXES.iter().find_map(|suffix| {
+        let mut candidate = path.as_os_str().to_owned();
+        candidate.push(suffix);
+        let candidate = PathBuf::from(candidate);
+        candidate.is_file().then_some(candidate)
+    });
+    // TS sources are commonly imported as `./foo.js`
+    found.or_else(|| match path.extension()?.to_str()? {
+        "js" | "jsx" | "mjs" => ["ts", "tsx", "mts"]
+            .iter()
+            .map(|ext| path.with_extension(ext))
+            .find(|candidate| candidate.is_file()),
+        _ => None,
+    })
+}
+
+pub fn is_external(specifier: &str) -> bool {
+    specifier.contains("://")
+        || specifier.starts_with("//")
+        || specifier.starts_with("data:")
+        || specifier.starts_with("blob:")
+}
+
+/// Reprints the bundle without whitespace and comments, with short local
+/// names.
+fn compact(code: &str, entry: &Path) -> Result<String, String> {
+    let allocator = Allocator::default();
+    let ret = Parser::new(&allocator, code, SourceType::mjs()).parse();
+    if let Some(error) = ret.errors.first() {
+        return Err(format!("{} (bundle): {}", entry.display(), error));
+    }
+    let mut scoping = SemanticBuilder::new()
+        .build(&ret.program)
+        .semantic
+        .into_scoping();
+    mangle(&mut scoping);
+    Ok(Codegen::new()
+        .with_options(CodegenOptions::minify())
+        .with_scoping(Some(scoping))
+        .build(&ret.program)
+        .code)
+}
+
+/// Gives every binding below the top level a short name of its own. Names
+/// are never reused, so no binding can shadow another or a global; top-level
+/// names stay as they are. Direct `eval` sees local names, so a bundle using
+/// it keeps them all.
+fn mangle(scoping: &mut Scoping) {
+    if scoping
+        .scope_descendants_from_root()
+        .any(|scope| scoping.scope_flags(scope).contains_direct_eval())
+    {
+        return;
+    }
+    let root = scoping.root_scope_id();
+    let mut taken: HashSet<String> = scoping
+        .root_unresolved_references()
+        .keys()
+        .map(|name| name.to_string())
+        .collect();
+    taken.extend(
+        scoping
+            .iter_bindings_in(root)
+            .map(|symbol| scoping.symbol_name(symbol).to_string()),
+    );
+
+    let mut symbols: Vec<SymbolId> = scoping
+        .symbol_ids()
+        .filter(|symbol| scoping.symbol_scope_id(*symbol) != root)
+        .collect();
+    // The most used get the shortest names
+    symbols
+        .sort_by_key(|symbol| std::cmp::Reverse(scoping.get_resolved_reference_ids(*symbol).len()));
+    let mut names = (0..)
+        .map(short_name)
+        .filter(|name| !taken.contains(name) && !RESERVED.contains(&name.as_str()));
+    for symbol in symbols {
+        if let Some(name) = names.next() {
+            scoping.set_symbol_name(symbol, &name);
+        }
+    }
+}
+
+/// `a`, `b`, ..., `$`, `_`, `aa`, `ba`, ...: a valid identifier for every `n`.
+fn short_name(mut n: usize) -> String {
+    const FIRST: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ$_";
+    const REST: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ$_0123456789";
+    let mut name = String::new();
+    name.push(FIRST[n % FIRST.len()] as char);
+    n /= FIRST.len();
+    while n > 0 {
+        n -= 1;
+        name.push(REST[n % REST.len()] as char);
+        n /= REST.len();
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_names_are_identifiers() {
+        assert_eq!(short_name(0), "a");
+        assert_eq!(short_name(53), "_");
+        assert_eq!(short_name(54), "aa");
+        assert_eq!(short_name(55), "ba");
+        let names: HashSet<String> = (0..5000).map(short_name).collect();
+        assert_eq!(names.len(), 5000);
+        assert!(names
+            .iter()
+            .all(|name| !name.as_bytes()[0].is_ascii_digit()));
+    }
+
+    #[test]
+    fn compact_mangles_locals_only() {
+        let code = "const top = 1;\n\
+            function outer(longName) { const inner = longName + top; return { inner, window }; }\n\
+            outer(2);";
+        let out = compact(code, Path::new("test.js")).unwrap();
+        assert!(out.contains("const top=1"), "{}", out);
+        assert!(out.contains("function outer(a)"), "{}", out);
+        assert!(out.contains("inner:"), "{}", out);
+        assert!(out.contains("window"), "{}", out);
+        assert!(!out.contains("longName"), "{}", out);
+    }
+
+    #[test]
+    fn compact_keeps_names_under_direct_eval() {
+        let code = "function f(local) { return eval('local'); }";
+        let out = compact(code, Path::new("test.js")).unwrap();
+        assert!(out.contains("local"), "{}", out);
+    }
+
+    #[test]
+    fn top_level_await_is_rejected() {
+        let path = Path::new("tla.js");
+        let mut bundler = Bundler::default();
+        assert!(bundler
+            .wrap_module("await Promise.resolve();", path)
+            .is_err());
+        assert!(bundler
+            .wrap_module("for await (const x of []) {}", path)
+            .is_err());
+        assert!(bundler
+            .wrap_module(
+                "async function f() { await g(); }\nconst h = async () => await f();",
+                path
+            )
+            .is_ok());
+    }
+}
@@ -0,0 +1,345 @@
+pub mod bundle;
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use regex::{Captures, Regex};
+
+use crate::http::routes::{find_preferred_index, project_root};
+use crate::http::{css, deps};
+
+const DEFAULT_OUT_DIR: &str = "dist";
+const ASSETS_DIR: &str = "assets";
+/// Written into every output directory; only directories holding it are
+/// ever emptied by a later build.
+const OUT_MARKER: &str = ".wss-build";
+
+/// Never copied to the output: sources that only exist for the dev pipeline.
+const SOURCE_EXTENSIONS: &[&str] = &["ts", "tsx", "jsx", "mts"];
+/// Never copied either, like lockfiles (`deps::is_lockfile`).
+const PROJECT_FILES: &[&str] = &[
+    "package.json",
+    "tsconfig.json",
+    "jsconfig.json",
+    "importmap.json",
+];
+
+/// Scripts the dev server injects; dropped if they ever end up in a page.
+const DEV_SCRIPT_MARKERS: &[&str] = &["hmr-client", "console-override", "data-wss-dev"];
+
+lazy_static::lazy_static! {
+    static ref SCRIPT_TAG: Regex = Regex::new(r"(?is)<script\b([^>]*)>(.*?)</script\s*>").unwrap();
+    static ref LINK_TAG: Regex = Regex::new(r"(?is)<link\b[^>]*>").unwrap();
+    static ref SRC_ATTR: Regex =
+        Regex::new(r#"(?i)(\s(?:src|href)\s*=\s*)("[^"]*"|'[^']*'|[^\s>]+)"#).unwrap();
+    static ref MODULE_TYPE: Regex =
+        Regex::new(r#"(?i)\stype\s*=\s*(?:"module"|'module'|module(?:[\s/]|$))"#).unwrap();
+}
+
+/// `wss_serve build [--out <dir>]`: bundles every HTML entry point of the
+/// project into a static folder.
+pub fn run(args: &[String]) -> io::Result<()> {
+    let out_dir = PathBuf::from(
+        args.iter()
+            .position(|arg| arg == "--out")
+            .and_then(|i| args.get(i + 1))
+            .map_or(DEFAULT_OUT_DIR, |dir| dir.as_str()),
+    );
+
+    prepare_out_dir(&out_dir)?;
+
+    let mut build = Build {
+        out_dir,
+        emitted: HashMap::new(),
+        failed: false,
+    };
+
+    let root = project_root();
+    let mut files = Vec::new();
+    collect_files(root, &build.out_dir, &mut files)?;
+
+    let entries: HashSet<PathBuf> = files
+        .iter()
+        .filter_map(|file| file.parent())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .filter_map(find_preferred_index)
+        .collect();
+    if entries.is_empty() {
+        eprintln!("[Build] No HTML entry point found in {}", root.display());
+    }
+
+    for entry in &entries {
+        build.page(entry)?;
+    }
+
+    let mut copied = 0;
+    for file in &files {
+        if entries.contains(file) || !is_copied_asset(file) {
+            continue;
+        }
+        let target = build.out_dir.join(file.strip_prefix(root).unwrap_or(file));
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::copy(file, &target)?;
+        copied += 1;
+    }
+
+    println!(
+        "[Build] {} page(s), {} hashed file(s), {} asset(s) copied -> {}",
+        entries.len(),
+        build.emitted.len(),
+        copied,
+        build.out_dir.display()
+    );
+    if build.failed {
+        return Err(io::Error::other("build finished with errors"));
+    }
+    Ok(())
+}
+
+struct Build {
+    out_dir: PathBuf,
+    /// Source file -> emitted `assets/...` path, so shared files are written once
+    emitted: HashMap<PathBuf, String>,
+    failed: bool,
+}
+
+impl Build {
+    /// Rewrites one HTML entry: local and inline module scripts become
+    /// bundles, local `<link>`s hashed copies, dev scripts disappear.
+    fn page(&mut self, html_path: &Path) -> io::Result<()> {
+        let html = fs::read_to_string(html_path)?;
+        let relative = html_path.strip_prefix(project_root()).unwrap_or(html_path);
+        // `../` back to the output root from the page's directory
+        let to_root = "../".repeat(relative.components().count().saturating_sub(1));
+
+        let html = SCRIPT_TAG.replace_all(&html, |caps: &Captures| {
+            // Only the opening tag's attributes; the body is code
+            let (attrs, body) = (&caps[1], &caps[2]);
+            if DEV_SCRIPT_MARKERS
+                .iter()
+                .any(|marker| attrs.contains(marker))
+            {
+                return String::new();
+            }
+            if SRC_ATTR.is_match(attrs) {
+                let attrs = self.rewrite_ref(attrs, html_path, &to_root, true);
+                return format!("<script{}>{}</script>", attrs, body);
+            }
+            if MODULE_TYPE.is_match(attrs) && !body.trim().is_empty() {
+                if let Some(emitted) = self.emit_inline(body, html_path) {
+                    return format!("<script{} src=\"{}{}\"></script>", attrs, to_root, emitted);
+                }
+            }
+            caps[0].to_string()
+        });
+        let html = LINK_TAG.replace_all(&html, |caps: &Captures| {
+            self.rewrite_ref(&caps[0], html_path, &to_root, false)
+        });
+
+        let target = self.out_dir.join(relative);
+        if let Some(dir) = target.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&target, html.as_ref())?;
+        println!("[Build] {}", target.display());
+        Ok(())
+    }
+
+    /// Points a tag's (or attribute list's) `src`/`href` at the emitted file,
+    /// if it is a local one.
+    fn rewrite_ref(&mut self, tag: &str, html_path: &Path, to_root: &str, script: bool) -> String {
+        let Some(caps) = SRC_ATTR.captures(tag) else {
+            return tag.to_string();
+        };
+        let value = caps[2].trim_matches(|c| c == '"' || c == '\'');
+        let Some(file) = local_file(value, html_path) else {
+            return tag.to_string();
+        };
+
+        match self.emit(&file, script) {
+            Some(emitted) => {
+                let attr = caps.get(0).unwrap();
+                format!(
+                    "{}{}\"{}{}\"{}",
+                    &tag[..attr.start()],
+                    &caps[1],
+                    to_root,
+                    emitted,
+                    &tag[attr.end()..]
+                )
+            }
+            None => tag.to_string(),
+        }
+    }
+
+    /// Bundles (scripts), processes (CSS) or copies (everything else) `file`
+    /// under a content-hashed name; returns the output-relative path.
+    fn emit(&mut self, file: &Path, script: bool) -> Option<String> {
+        if let Some(emitted) = self.emitted.get(file) {
+            return Some(emitted.clone());
+        }
+
+        let (content, ext) = if script {
+            match bundle::bundle(file) {
+                Ok(code) => (code.into_bytes(), "js".to_string()),
+                Err(e) => {
+                    eprintln!("[Build] {}", e);
+                    self.failed = true;
+                    return None;
+                }
+            }
+        } else if file.extension().is_some_and(|ext| ext == "css") {
+            // Hashed url() assets sit next to the stylesheet in assets/
+            let processed = css::process(file, true, &mut |asset| {
+                self.emit(asset, false)
+                    .map(|emitted| emitted.trim_start_matches("assets/").to_string())
+            });
+            if let Some(d) = processed.diagnostics.first() {
+                eprintln!("[Build] {}:{}:{}: {}", d.file, d.line, d.column, d.message);
+                self.failed = true;
+                return None;
+            }
+            (processed.code.into_bytes(), "css".to_string())
+        } else {
+            match fs::read(file) {
+                Ok(content) => {
+                    let ext = file.extension().and_then(|e| e.to_str()).unwrap_or("");
+                    (content, ext.to_string())
+                }
+                Err(e) => {
+                    eprintln!("[Build] {}: {}", file.display(), e);
+                    self.failed = true;
+                    return None;
+                }
+            }
+        };
+
+        let emitted = self.write_hashed(file, &content, &ext)?;
+        self.emitted.insert(file.to_path_buf(), emitted.clone());
+        Some(emitted)
+    }
+
+    /// Bundles the body of an inline module script of `html_path`.
+    fn emit_inline(&mut self, code: &str, html_path: &Path) -> Option<String> {
+        match bundle::bundle_source(code, html_path) {
+            Ok(code) => self.write_hashed(html_path, code.as_bytes(), "js"),
+            Err(e) => {
+                eprintln!("[Build] {} (inline script): {}", html_path.display(), e);
+                self.failed = true;
+                None
+            }
+        }
+    }
+
+    /// Writes `content` to `assets/<stem of file>-<hash>.<ext>`.
+    fn write_hashed(&mut self, file: &Path, content: &[u8], ext: &str) -> Option<String> {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        let stem = file.file_stem().and_then(|s| s.to_str()).unwrap_or("asset");
+        let name = format!("{}-{:08x}.{}", stem, hasher.finish() as u32, ext);
+        let emitted = format!("{}/{}", ASSETS_DIR, name);
+
+        if let Err(e) = fs::write(self.out_dir.join(&emitted), content) {
+            eprintln!("[Build] Failed to write {}: {}", emitted, e);
+            self.failed = true;
+            return None;
+        }
+        Some(emitted)
+    }
+}
+
+/// Project file behind a `src`/`href` value, for local references only.
+fn local_file(value: &str, html_path: &Path) -> Option<PathBuf> {
+    let value = value.split(['?', '#']).next()?;
+    if value.is_empty() || bundle::is_external(value) {
+        return None;
+    }
+    let file = if let Some(rest) = value.strip_prefix("/project/") {
+        project_root().join(rest)
+    } else if let Some(rest) = value.strip_prefix('/') {
+        project_root().join(rest)
+    } else {
+        html_path.parent()?.join(value)
+    };
+    let file = crate::http::resolve::normalize(&file);
+    file.is_file().then_some(file)
+}
+
+fn is_copied_asset(file: &Path) -> bool {
+    let ext = file.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let name = file.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    !SOURCE_EXTENSIONS.contains(&ext) && !PROJECT_FILES.contains(&name) && !deps::is_lockfile(file)
+}
+
+/// All project files, skipping `node_modules`, dot-directories and the output.
+/// Empties `out_dir` for a fresh build, refusing anything that may hold files
+/// other than a previous build's output.
+fn prepare_out_dir(out_dir: &Path) -> io::Result<()> {
+    if out_dir.exists() {
+        let out = fs::canonicalize(out_dir)?;
+        let cwd = env::current_dir().and_then(fs::canonicalize)?;
+        let root = fs::canonicalize(project_root())?;
+        let refuse = |reason: &str| {
+            Err(io::Error::other(format!(
+                "refusing to replace {}: {}",
+                out_dir.display(),
+                reason
+            )))
+        };
+        if cwd.starts_with(&out) {
+            return refuse("it contains the working directory");
+        }
+        if root.starts_with(&out) {
+            return refuse("it contains the project");
+        }
+        let empty = fs::read_dir(&out)?.next().is_none();
+        if !empty && !out.join(OUT_MARKER).is_file() {
+            return refuse(&format!("it isn't empty and has no {} marker", OUT_MARKER));
+        }
+        fs::remove_dir_all(&out)?;
+    }
+    fs::create_dir_all(out_dir.join(ASSETS_DIR))?;
+    fs::write(
+        out_dir.join(OUT_MARKER),
+        "Output of `wss_serve build`; emptied on the next build.\n",
+    )
+}
+
+fn collect_files(dir: &Path, out_dir: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+        if path.is_dir() {
+            if name == "node_modules" || name.starts_with('.') || path == out_dir {
+                continue;
+            }
+            collect_files(&path, out_dir, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_type_attribute() {
+        assert!(MODULE_TYPE.is_match(r#" type="module""#));
+        assert!(MODULE_TYPE.is_match(" async type=module"));
+        assert!(MODULE_TYPE.is_match(" TYPE='module' defer"));
+        assert!(!MODULE_TYPE.is_match(r#" type="modules""#));
+        assert!(!MODULE_TYPE.is_match(r#" data-type="module""#));
+        assert!(!MODULE_TYPE.is_match(r#" type="text/javascript""#));
+    }
+}
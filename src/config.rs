@@ -0,0 +1,294 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+
+use crate::http::transform_options::TransformSettings;
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// How source maps for transpiled modules are delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceMapMode {
+    /// `//# sourceMappingURL=data:...` appended to the module
+    Inline,
+    /// Separate `<file>.map` response, referenced by URL and `SourceMap` header
+    Sidecar,
+    Off,
+}
+
+/// What a client's outgoing queue does when it's full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// Discard the oldest queued event; replies to the client's own requests
+    /// are kept, and a queue of nothing but replies disconnects
+    DropOldest,
+    /// Replace a queued duplicate of the same HMR/watcher event, else drop
+    /// like `DropOldest`
+    Coalesce,
+    /// Close the connection; the client reconnects and starts afresh
+    Disconnect,
+}
+
+/// `SPA_FALLBACK` entry: missing pages under `prefix` (relative to the
+/// project, `""` = everywhere) are served `page`, by default the prefix
+/// directory's index page.
+#[derive(Debug, Clone)]
+pub struct FallbackRule {
+    pub prefix: String,
+    pub page: Option<String>,
+}
+
+/// Server settings read from the environment (`.env` is loaded in `main`).
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Optional directory where transpiled modules are persisted between runs.
+    pub transpile_cache_dir: Option<PathBuf>,
+    pub source_maps: SourceMapMode,
+    /// Transform defaults, overridden per directory by tsconfig/jsconfig
+    pub transform: TransformSettings,
+    /// Browsers CSS is lowered for, e.g. `chrome100,safari15` (`esnext` = none)
+    pub css_targets: String,
+    /// Serve the injected runtime from this directory instead of the copy
+    /// embedded at build time (for working on the runtime itself)
+    pub inject_scripts_dir: Option<PathBuf>,
+    /// List `/project` directories that have no index page (off by default)
+    pub directory_listing: bool,
+    /// History-API fallback for client-side routed apps
+    pub spa_fallback: Vec<FallbackRule>,
+    /// JSON proxy table (path prefix -> upstream), see `http::proxy`
+    pub proxy_config: Option<PathBuf>,
+    /// Served under `/project/`; relative if inside the working directory
+    pub project_dir: PathBuf,
+    /// Serve the IDE from this directory instead of the copy embedded at
+    /// build time
+    pub web_dir: Option<PathBuf>,
+    /// Separate origin for `/project` (another port, or e.g.
+    /// `http://preview.localhost:8080`), so previewed code can't script the IDE
+    pub preview_origin: Option<String>,
+    /// Origins the IDE is opened at: allowed to frame the preview and to
+    /// receive its `postMessage`s
+    pub ide_origins: Vec<String>,
+    /// COOP/COEP on every response, so both pages get `SharedArrayBuffer`
+    pub cross_origin_isolation: bool,
+    /// Extra CSP directives for preview responses, e.g. `script-src 'self'`
+    pub preview_csp: Option<String>,
+    /// Preview console entries kept for IDE clients that connect later
+    pub console_history: usize,
+    /// Messages queued per WebSocket client before `client_queue_policy`
+    pub client_queue_size: usize,
+    pub client_queue_policy: QueuePolicy,
+    /// Between server pings to each WebSocket client; `None` = no pings
+    pub heartbeat_interval: Option<Duration>,
+    /// Silence (no pong or other frame) after which a client is dropped
+    pub heartbeat_timeout: Duration,
+}
+
+impl Config {
+    fn from_env() -> Self {
+        Config {
+            transpile_cache_dir: env::var("TRANSPILE_CACHE_DIR")
+                .ok()
+                .filter(|dir| !dir.trim().is_empty())
+                .map(PathBuf::from),
+            source_maps: match env::var("SOURCE_MAPS").as_deref() {
+                Ok("inline") => SourceMapMode::Inline,
+                Ok("off") | Ok("false") => SourceMapMode::Off,
+                _ => SourceMapMode::Sidecar,
+            },
+            transform: TransformSettings::from_env(),
+            // Defaults predate native CSS nesting, so nesting is always lowered
+            css_targets: env::var("CSS_TARGETS")
+                .unwrap_or_else(|_| "chrome100,edge100,firefox100,safari15".to_string()),
+            inject_scripts_dir: asset_dir("INJECT_SCRIPTS_DIR", "inject_scripts"),
+            directory_listing: matches!(
+                env::var("DIRECTORY_LISTING").as_deref(),
+                Ok("true") | Ok("on") | Ok("1")
+            ),
+            spa_fallback: env::var("SPA_FALLBACK")
+                .map(|rules| parse_fallback_rules(&rules))
+                .unwrap_or_default(),
+            proxy_config: env::var("PROXY_CONFIG")
+                .ok()
+                .filter(|path| !path.trim().is_empty())
+                .map(PathBuf::from),
+            project_dir: env::var("PROJECT_DIR")
+                .ok()
+                .filter(|dir| !dir.trim().is_empty())
+                .map_or_else(|| PathBuf::from("project"), |dir| project_dir(&dir)),
+            web_dir: asset_dir("WEB_DIR", "web"),
+            preview_origin: env::var("PREVIEW_ORIGIN")
+                .ok()
+                .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                .filter(|origin| !origin.is_empty()),
+            ide_origins: env::var("IDE_ORIGIN")
+                .ok()
+                .map(|origins| {
+                    origins
+                        .split(',')
+                        .map(|origin| origin.trim().trim_end_matches('/').to_string())
+                        .filter(|origin| !origin.is_empty())
+                        .collect::<Vec<_>>()
+                })
+                .filter(|origins| !origins.is_empty())
+                .unwrap_or_else(default_ide_origins),
+            cross_origin_isolation: matches!(
+                env::var("CROSS_ORIGIN_ISOLATION").as_deref(),
+                Ok("true") | Ok("on") | Ok("1")
+            ),
+            preview_csp: env::var("PREVIEW_CSP")
+                .ok()
+                .map(|csp| csp.trim().trim_matches(';').to_string())
+                .filter(|csp| !csp.is_empty()),
+            console_history: env::var("CONSOLE_HISTORY")
+                .ok()
+                .and_then(|n| n.trim().parse().ok())
+                .unwrap_or(500),
+            client_queue_size: env::var("CLIENT_QUEUE_SIZE")
+                .ok()
+                .and_then(|n| n.trim().parse().ok())
+                .filter(|n| *n > 0)
+                .unwrap_or(256),
+            client_queue_policy: match env::var("CLIENT_QUEUE_POLICY").as_deref() {
+                Ok("drop-oldest") => QueuePolicy::DropOldest,
+                Ok("disconnect") => QueuePolicy::Disconnect,
+                _ => QueuePolicy::Coalesce,
+            },
+            heartbeat_interval: Some(seconds("HEARTBEAT_INTERVAL", 15))
+                .filter(|interval| !interval.is_zero()),
+            heartbeat_timeout: seconds("HEARTBEAT_TIMEOUT", 45),
+        }
+    }
+}
+
+/// Whole seconds from `var`.
+fn seconds(var: &str, default: u64) -> Duration {
+    Duration::from_secs(
+        env::var(var)
+            .ok()
+            .and_then(|n| n.trim().parse().ok())
+            .unwrap_or(default),
+    )
+}
+
+/// `HOST:PORT`, under every name a loopback address is commonly opened by.
+fn default_ide_origins() -> Vec<String> {
+    let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
+    let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
+    let hosts: &[&str] = match host.as_str() {
+        "127.0.0.1" | "localhost" | "0.0.0.0" => &["127.0.0.1", "localhost"],
+        "::1" | "::" => &["[::1]", "localhost"],
+        other => return vec![format!("http://{}:{}", other, port)],
+    };
+    hosts
+        .iter()
+        .map(|host| format!("http://{}:{}", host, port))
+        .collect()
+}
+
+/// Directory overriding an embedded tree; without the `embed` feature there
+/// is nothing embedded, so it defaults to `default` in the working directory.
+fn asset_dir(var: &str, default: &str) -> Option<PathBuf> {
+    env::var(var)
+        .ok()
+        .filter(|dir| !dir.trim().is_empty())
+        .map(PathBuf::from)
+        .or_else(|| (!cfg!(feature = "embed")).then(|| PathBuf::from(default)))
+}
+
+/// Module and cache keys are paths relative to the working directory, so a
+/// project inside it stays relative; anything else becomes absolute.
+fn project_dir(dir: &str) -> PathBuf {
+    let Ok(dir) = fs::canonicalize(dir) else {
+        return PathBuf::from(dir);
+    };
+    match env::current_dir().and_then(fs::canonicalize) {
+        Ok(cwd) => relative_to(dir, &cwd),
+        Err(_) => dir,
+    }
+}
+
+/// `dir` below `cwd` if it's inside it; the working directory itself is `.`
+/// rather than an empty path, which can't be read or joined onto usefully.
+fn relative_to(dir: PathBuf, cwd: &Path) -> PathBuf {
+    match dir.strip_prefix(cwd) {
+        Ok(rest) if rest.as_os_str().is_empty() => PathBuf::from("."),
+        Ok(rest) => rest.to_path_buf(),
+        Err(_) => dir,
+    }
+}
+
+/// `/,/admin/=admin/app.html` -> rules for `""` and `"admin/"`.
+fn parse_fallback_rules(rules: &str) -> Vec<FallbackRule> {
+    rules
+        .split(',')
+        .map(str::trim)
+        .filter(|rule| !rule.is_empty())
+        .map(|rule| {
+            let (prefix, page) = match rule.split_once('=') {
+                Some((prefix, page)) => (prefix, Some(page.trim().trim_start_matches('/'))),
+                None => (rule, None),
+            };
+            let prefix = prefix.trim().trim_matches('/');
+            FallbackRule {
+                // Whole segments only: `app/` must not match `apple/`
+                prefix: if prefix.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}/", prefix)
+                },
+                page: page.filter(|page| !page.is_empty()).map(str::to_string),
+            }
+        })
+        .collect()
+}
+
+/// Global config, initialised from the environment on first access.
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(Config::from_env)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn project_dir_relative_to_working_dir() {
+        let cwd = Path::new("/work");
+        assert_eq!(relative_to(PathBuf::from("/work"), cwd), Path::new("."));
+        assert_eq!(
+            relative_to(PathBuf::from("/work/site"), cwd),
+            Path::new("site")
+        );
+        assert_eq!(
+            relative_to(PathBuf::from("/elsewhere/site"), cwd),
+            Path::new("/elsewhere/site")
+        );
+        assert_eq!(project_dir("."), Path::new("."));
+        assert_eq!(project_dir("src"), Path::new("src"));
+    }
+
+    fn rules(rules: &str) -> Vec<(String, Option<String>)> {
+        parse_fallback_rules(rules)
+            .into_iter()
+            .map(|rule| (rule.prefix, rule.page))
+            .collect()
+    }
+
+    #[test]
+    fn fallback_rules() {
+        assert_eq!(rules("/"), [("".into(), None)]);
+        assert_eq!(
+            rules(" /, /admin/ = /admin/app.html ,"),
+            [
+                ("".into(), None),
+                ("admin/".into(), Some("admin/app.html".into()))
+            ]
+        );
+        assert_eq!(rules("app"), [("app/".into(), None)]);
+        assert_eq!(rules("docs/v1/="), [("docs/v1/".into(), None)]);
+        assert!(rules(" , ").is_empty());
+    }
+}
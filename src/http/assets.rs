@@ -0,0 +1,200 @@
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::Path;
+
+use serde_json::Value;
+
+use super::diagnostics::{self, Diagnostic};
+use super::resolve::{self, quote};
+
+/// Imported as their URL by default: `import logo from "./logo.png"`.
+pub const ASSET_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "svg", "webp", "avif", "ico", "bmp", "woff", "woff2", "ttf",
+    "otf", "eot", "mp4", "webm", "ogg", "mp3", "wav", "flac", "aac", "pdf", "wasm",
+];
+
+/// How a non-JS import becomes a module.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportKind {
+    /// `?raw`: the file's text as the default export
+    Raw,
+    /// `?url` (implied for assets): the file's URL as the default export
+    Url,
+    /// `.json`: the parsed value as the default export
+    Json,
+    /// `.css`: injected `<style>`, class map for CSS modules (see `css`)
+    Css,
+}
+
+/// Import kind from an explicit query flag, else from the file extension.
+/// `None` for ordinary JS modules.
+pub fn import_kind(url: &str) -> Option<ImportKind> {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let flags: Vec<&str> = query.split('&').collect();
+    if flags.contains(&"raw") {
+        return Some(ImportKind::Raw);
+    }
+    if flags.contains(&"url") {
+        return Some(ImportKind::Url);
+    }
+    let ext = Path::new(path.split('#').next().unwrap_or(path))
+        .extension()
+        .and_then(|e| e.to_str())?
+        .to_ascii_lowercase();
+    match ext.as_str() {
+        "css" => Some(ImportKind::Css),
+        "json" => Some(ImportKind::Json),
+        ext if ASSET_EXTENSIONS.contains(&ext) => Some(ImportKind::Url),
+        _ => None,
+    }
+}
+
+/// Adds the query flag the `/project` route keys on (`?import` for CSS and
+/// JSON, `?url` for assets) and, for files other than CSS, a `v=` version so
+/// an importer re-fetched after a change doesn't reuse the old module.
+pub fn tag_import(url: String, file: Option<&Path>) -> String {
+    let Some(kind) = import_kind(&url) else {
+        return url;
+    };
+    if url.contains("://") {
+        return url;
+    }
+
+    let (path, query) = match url.split_once('?') {
+        Some((path, query)) => (path.to_string(), query.to_string()),
+        None => (url.clone(), String::new()),
+    };
+    let mut flags: Vec<String> = query
+        .split('&')
+        .filter(|flag| !flag.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    let flag = match kind {
+        ImportKind::Css | ImportKind::Json => "import",
+        ImportKind::Url => "url",
+        ImportKind::Raw => "raw",
+    };
+    if !flags.iter().any(|f| f == flag) {
+        flags.insert(0, flag.to_string());
+    }
+    if kind != ImportKind::Css {
+        if let Some(version) = file.and_then(version) {
+            flags.retain(|f| !f.starts_with("v="));
+            flags.push(format!("v={}", version));
+        }
+    }
+    format!("{}?{}", path, flags.join("&"))
+}
+
+/// `/project` response body for `?raw`, `?url` and JSON `?import` requests.
+pub fn module_for(path: &Path, query: &str) -> Option<io::Result<String>> {
+    let flags: Vec<&str> = query.split('&').collect();
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+
+    if flags.contains(&"raw") {
+        return Some(fs::read(path).map(|bytes| {
+            format!(
+                "export default {};\n",
+                quote(&String::from_utf8_lossy(&bytes))
+            )
+        }));
+    }
+    if flags.contains(&"url") {
+        return Some(Ok(format!(
+            "export default {};\n",
+            quote(&resolve::file_url(path))
+        )));
+    }
+    if flags.contains(&"import") && ext.eq_ignore_ascii_case("json") {
+        return Some(fs::read_to_string(path).map(|text| json_module(&text, path)));
+    }
+    None
+}
+
+fn json_module(text: &str, path: &Path) -> String {
+    match serde_json::from_str::<Value>(text) {
+        Ok(_) => format!("export default {};\n", text.trim()),
+        Err(e) => diagnostics::error_module(&[Diagnostic::at(
+            path,
+            text,
+            e.line(),
+            e.column(),
+            e.to_string(),
+        )]),
+    }
+}
+
+/// Short hash of the file's mtime and size.
+fn version(file: &Path) -> Option<String> {
+    let meta = fs::metadata(file).ok()?;
+    let mut hasher = DefaultHasher::new();
+    meta.modified().ok().hash(&mut hasher);
+    meta.len().hash(&mut hasher);
+    Some(format!("{:08x}", hasher.finish() as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn import_kind_by_flag_then_extension() {
+        assert_eq!(import_kind("./a.txt?raw"), Some(ImportKind::Raw));
+        assert_eq!(import_kind("./a.css?raw"), Some(ImportKind::Raw));
+        assert_eq!(import_kind("./a.js?url"), Some(ImportKind::Url));
+        assert_eq!(import_kind("./logo.PNG"), Some(ImportKind::Url));
+        assert_eq!(
+            import_kind("./data.json?import&v=1"),
+            Some(ImportKind::Json)
+        );
+        assert_eq!(import_kind("./a.module.css"), Some(ImportKind::Css));
+        assert_eq!(import_kind("./a.ts"), None);
+        assert_eq!(import_kind("./a"), None);
+        // A flag-like word elsewhere in the query isn't a flag
+        assert_eq!(import_kind("./a.js?format=raw"), None);
+    }
+
+    #[test]
+    fn tag_import_adds_flags() {
+        assert_eq!(tag_import("./a.css".into(), None), "./a.css?import");
+        assert_eq!(tag_import("./d.json".into(), None), "./d.json?import");
+        assert_eq!(tag_import("./logo.svg".into(), None), "./logo.svg?url");
+        assert_eq!(tag_import("./t.md?raw".into(), None), "./t.md?raw");
+        assert_eq!(tag_import("./a.ts".into(), None), "./a.ts");
+        assert_eq!(
+            tag_import("https://cdn/x.css".into(), None),
+            "https://cdn/x.css"
+        );
+        assert_eq!(
+            tag_import("./a.css?inline".into(), None),
+            "./a.css?import&inline"
+        );
+    }
+
+    #[test]
+    fn tag_import_versions_non_css() {
+        let file = std::env::temp_dir().join(format!("wss-assets-{}.json", std::process::id()));
+        fs::write(&file, "{}").unwrap();
+        let version = version(&file).unwrap();
+
+        assert_eq!(
+            tag_import("./d.json?v=old".into(), Some(&file)),
+            format!("./d.json?import&v={}", version)
+        );
+        // Stylesheets are hot-swapped instead
+        assert_eq!(tag_import("./a.css".into(), Some(&file)), "./a.css?import");
+        fs::remove_file(&file).unwrap();
+    }
+
+    #[test]
+    fn json_module_checks_syntax() {
+        assert_eq!(
+            json_module(" {\"a\": 1}\n", Path::new("d.json")),
+            "export default {\"a\": 1};\n"
+        );
+        assert!(json_module("{\"a\": }", Path::new("d.json")).contains("d.json"));
+    }
+}
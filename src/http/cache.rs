@@ -0,0 +1,233 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use bytes::Bytes;
+
+use super::compress::{self, Encoding};
+use super::diagnostics::Diagnostic;
+use super::resolve;
+use super::transform_options::TransformSettings;
+use super::transpile::transform_module;
+use crate::config::config;
+
+/// A transpiled and import-rewritten module, ready to be served.
+pub struct CachedModule {
+    pub code: Bytes,
+    /// Source map JSON, unless `SOURCE_MAPS=off`
+    pub map: Option<Bytes>,
+    /// Strong ETag value (without quotes) derived from path, content, options
+    /// and the resolved imports.
+    pub etag: String,
+    /// Errors from the last transpile; `code` is then an error module.
+    pub diagnostics: Vec<Diagnostic>,
+    mtime: Option<SystemTime>,
+    len: u64,
+    content_hash: u64,
+    /// Disk cache key of `code` itself, i.e. after import rewriting
+    code_key: u64,
+    /// `code` per content encoding, produced on first request
+    compressed: Mutex<HashMap<Encoding, Bytes>>,
+}
+
+impl CachedModule {
+    /// `code` compressed with `encoding`, also kept next to the disk cache.
+    pub fn compressed(&self, encoding: Encoding) -> Option<Bytes> {
+        if let Some(bytes) = self.compressed.lock().unwrap().get(&encoding) {
+            return Some(bytes.clone());
+        }
+        // Error modules aren't persisted, so neither are their compressed forms
+        let persist = self.diagnostics.is_empty();
+        let ext = format!("js.{}", encoding.extension());
+        let bytes = match read_disk(self.code_key, &ext).filter(|_| persist) {
+            Some(bytes) => bytes,
+            None => {
+                let bytes = match compress::compress(&self.code, encoding) {
+                    Ok(bytes) => Bytes::from(bytes),
+                    Err(e) => {
+                        eprintln!("[Cache] Failed to compress ({}): {}", encoding.name(), e);
+                        return None;
+                    }
+                };
+                if persist {
+                    write_disk(self.code_key, &ext, &bytes);
+                }
+                bytes
+            }
+        };
+        self.compressed
+            .lock()
+            .unwrap()
+            .insert(encoding, bytes.clone());
+        Some(bytes)
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref TRANSPILE_CACHE: Mutex<HashMap<PathBuf, Arc<CachedModule>>> =
+        Mutex::new(HashMap::new());
+}
+
+/// Returns the transpiled output for `path`, reusing a cached result when the
+/// file's mtime/size or content hash is unchanged.
+pub fn transpiled(path: &Path) -> io::Result<Arc<CachedModule>> {
+    let meta = fs::metadata(path)?;
+    let mtime = meta.modified().ok();
+    let len = meta.len();
+
+    let cached = TRANSPILE_CACHE.lock().unwrap().get(path).cloned();
+
+    // Fast path: untouched file, no read needed
+    if let Some(entry) = &cached {
+        if entry.mtime.is_some() && entry.mtime == mtime && entry.len == len {
+            return Ok(entry.clone());
+        }
+    }
+
+    let source = fs::read_to_string(path)?;
+    let content_hash = hash_content(&source);
+
+    // Touched but identical content (e.g. editor save without changes)
+    if let Some(entry) = cached.filter(|e| e.content_hash == content_hash) {
+        return Ok(store(
+            path,
+            CachedModule {
+                code: entry.code.clone(),
+                map: entry.map.clone(),
+                etag: entry.etag.clone(),
+                diagnostics: entry.diagnostics.clone(),
+                mtime,
+                len,
+                content_hash,
+                code_key: entry.code_key,
+                compressed: Mutex::new(entry.compressed.lock().unwrap().clone()),
+            },
+        ));
+    }
+
+    let settings = TransformSettings::for_file(path);
+    let key = cache_key(path, content_hash, &settings);
+    let (code, map, diagnostics) = match read_disk(key, "js") {
+        Some(code) => (code, read_disk(key, "js.map"), Vec::new()),
+        None => {
+            let transpiled = transform_module(&source, path, &settings);
+            // Only clean output is persisted, so diagnostics are always re-reported
+            if transpiled.diagnostics.is_empty() {
+                write_disk(key, "js", transpiled.code.as_bytes());
+                if let Some(map) = &transpiled.map {
+                    write_disk(key, "js.map", map.as_bytes());
+                }
+            }
+            (
+                Bytes::from(transpiled.code),
+                transpiled.map.map(Bytes::from),
+                transpiled.diagnostics,
+            )
+        }
+    };
+
+    // Resolutions depend on node_modules, lockfiles and importmap.json, and
+    // rewriting records the module graph, so it's redone on every load
+    let code = if diagnostics.is_empty() {
+        Bytes::from(resolve::rewrite_imports(
+            &String::from_utf8_lossy(&code),
+            path,
+        ))
+    } else {
+        code
+    };
+    let code_key = {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        code.hash(&mut hasher);
+        hasher.finish()
+    };
+
+    Ok(store(
+        path,
+        CachedModule {
+            code,
+            map,
+            etag: format!("{:016x}", code_key),
+            diagnostics,
+            mtime,
+            len,
+            content_hash,
+            code_key,
+            compressed: Mutex::new(HashMap::new()),
+        },
+    ))
+}
+
+/// Drops the cached output for a project-relative path (called by the watcher).
+pub fn invalidate(path: &Path) {
+    let mut cache = TRANSPILE_CACHE.lock().unwrap();
+    if cache.remove(path).is_some() {
+        println!("[Cache] Invalidated: {}", path.display());
+    }
+}
+
+/// Drops everything, e.g. after a tsconfig change affects every module.
+pub fn clear() {
+    TRANSPILE_CACHE.lock().unwrap().clear();
+    println!("[Cache] Cleared");
+}
+
+fn store(path: &Path, module: CachedModule) -> Arc<CachedModule> {
+    let module = Arc::new(module);
+    TRANSPILE_CACHE
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), module.clone());
+    module
+}
+
+fn hash_content(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// path + content hash + transform options (+ source map mode, which changes
+/// output). Import resolution isn't part of the persisted output, so it needn't
+/// be part of the key.
+fn cache_key(path: &Path, content_hash: u64, settings: &TransformSettings) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    content_hash.hash(&mut hasher);
+    settings.hash(&mut hasher);
+    format!("{:?}", config().source_maps).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn disk_path(key: u64, ext: &str) -> Option<PathBuf> {
+    config()
+        .transpile_cache_dir
+        .as_ref()
+        .map(|dir| dir.join(format!("{:016x}.{}", key, ext)))
+}
+
+fn read_disk(key: u64, ext: &str) -> Option<Bytes> {
+    let path = disk_path(key, ext)?;
+    fs::read(path).ok().map(Bytes::from)
+}
+
+fn write_disk(key: u64, ext: &str, code: &[u8]) {
+    let Some(path) = disk_path(key, ext) else {
+        return;
+    };
+    if let Some(dir) = path.parent() {
+        if let Err(e) = fs::create_dir_all(dir) {
+            eprintln!("[Cache] Failed to create {}: {}", dir.display(), e);
+            return;
+        }
+    }
+    if let Err(e) = fs::write(&path, code) {
+        eprintln!("[Cache] Failed to write {}: {}", path.display(), e);
+    }
+}
@@ -0,0 +1,455 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use lightningcss::bundler::{Bundler, FileProvider, ResolveResult, SourceProvider};
+use lightningcss::css_modules::{self, CssModuleReference};
+use lightningcss::dependencies::{Dependency, DependencyOptions};
+use lightningcss::error::ErrorLocation;
+use lightningcss::stylesheet::{MinifyOptions, ParserFlags, ParserOptions, PrinterOptions};
+use lightningcss::targets::{Browsers, Targets};
+
+use super::deps;
+use super::diagnostics::Diagnostic;
+use super::resolve::{self, quote};
+use super::routes::project_root;
+use crate::config::config;
+
+/// A stylesheet with its `@import`s inlined and syntax lowered for the
+/// configured targets.
+pub struct ProcessedCss {
+    pub code: String,
+    /// `*.module.css` only: local class name -> generated class list
+    pub classes: Option<BTreeMap<String, String>>,
+    /// Strong ETag value (without quotes), a hash of `code`
+    pub etag: String,
+    /// Errors from the last run; `code` is then empty.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Every file the output was built from (entry first) and its mtime then
+    files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+lazy_static::lazy_static! {
+    static ref CSS_CACHE: Mutex<HashMap<PathBuf, Arc<ProcessedCss>>> =
+        Mutex::new(HashMap::new());
+}
+
+pub fn is_module(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|name| name.ends_with(".module.css"))
+}
+
+/// Dev-server output for `path`, reused while neither it nor anything it
+/// `@import`s has changed. `url()`s point at `/project/...` URLs.
+pub fn processed(path: &Path) -> io::Result<Arc<ProcessedCss>> {
+    fs::metadata(path)?;
+    if let Some(entry) = CSS_CACHE.lock().unwrap().get(path) {
+        if entry
+            .files
+            .iter()
+            .all(|(file, mtime)| modified(file) == *mtime)
+        {
+            return Ok(entry.clone());
+        }
+    }
+
+    let processed = Arc::new(process(path, false, &mut |file| {
+        Some(resolve::file_url(file))
+    }));
+    CSS_CACHE
+        .lock()
+        .unwrap()
+        .insert(path.to_path_buf(), processed.clone());
+    Ok(processed)
+}
+
+/// Cached stylesheets built from `changed` (itself included), dropping their
+/// entries. The watcher sends these so clients refresh only affected links.
+pub fn dependents(changed: &Path) -> Vec<PathBuf> {
+    let mut cache = CSS_CACHE.lock().unwrap();
+    let mut found: Vec<PathBuf> = cache
+        .iter()
+        .filter(|(_, css)| css.files.iter().any(|(file, _)| file == changed))
+        .map(|(path, _)| path.clone())
+        .collect();
+    for path in &found {
+        cache.remove(path);
+    }
+    if !found.iter().any(|path| path == changed) {
+        found.push(changed.to_path_buf());
+    }
+    found
+}
+
+/// Runs the pipeline: bundle `@import`s, CSS modules for `*.module.css`,
+/// lower nesting etc. for `CSS_TARGETS`. `url_for` maps each local `url()`
+/// asset to the URL written in its place (`None` keeps the original).
+pub fn process(
+    path: &Path,
+    minify: bool,
+    url_for: &mut dyn FnMut(&Path) -> Option<String>,
+) -> ProcessedCss {
+    let provider = ProjectProvider {
+        files: FileProvider::new(),
+        read: Mutex::new(Vec::new()),
+    };
+    let options = ParserOptions {
+        filename: path.display().to_string(),
+        css_modules: is_module(path).then(css_modules::Config::default),
+        flags: ParserFlags::NESTING,
+        ..ParserOptions::default()
+    };
+    let targets = targets();
+
+    let result = Bundler::new(&provider, None, options)
+        .bundle(path)
+        .map_err(|e| (e.kind.to_string(), e.loc))
+        .and_then(|mut stylesheet| {
+            stylesheet
+                .minify(MinifyOptions {
+                    targets,
+                    ..MinifyOptions::default()
+                })
+                .map_err(|e| (e.kind.to_string(), e.loc))?;
+            stylesheet
+                .to_css(PrinterOptions {
+                    minify,
+                    targets,
+                    analyze_dependencies: Some(DependencyOptions {
+                        remove_imports: true,
+                    }),
+                    ..PrinterOptions::default()
+                })
+                .map_err(|e| (e.kind.to_string(), e.loc))
+        });
+
+    let mut files = vec![(path.to_path_buf(), modified(path))];
+    files.extend(
+        provider
+            .read
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .filter(|file| file != path)
+            .map(|file| {
+                let mtime = modified(&file);
+                (file, mtime)
+            }),
+    );
+
+    let (code, classes, diagnostics) = match result {
+        Ok(output) => {
+            let mut code = output.code;
+            for dependency in output.dependencies.into_iter().flatten() {
+                if let Dependency::Url(url) = dependency {
+                    let replacement = local_asset(&url.url, Path::new(&url.loc.file_path))
+                        .and_then(|file| url_for(&file))
+                        .unwrap_or(url.url);
+                    code = code.replace(&url.placeholder, &replacement.replace(' ', "%20"));
+                }
+            }
+            let classes = output.exports.map(|exports| {
+                exports
+                    .into_iter()
+                    .map(|(local, export)| {
+                        let mut names = vec![export.name];
+                        for reference in export.composes {
+                            match reference {
+                                CssModuleReference::Local { name }
+                                | CssModuleReference::Global { name } => names.push(name),
+                                CssModuleReference::Dependency { .. } => {}
+                            }
+                        }
+                        (local, names.join(" "))
+                    })
+                    .collect()
+            });
+            (code, classes, Vec::new())
+        }
+        Err((message, loc)) => {
+            eprintln!("[CSS] {}: {}", path.display(), message);
+            (String::new(), None, vec![diagnostic(path, message, loc)])
+        }
+    };
+
+    let mut hasher = DefaultHasher::new();
+    code.hash(&mut hasher);
+    classes.hash(&mut hasher);
+
+    ProcessedCss {
+        code,
+        classes,
+        etag: format!("{:016x}", hasher.finish()),
+        diagnostics,
+        files,
+    }
+}
+
+/// JS module for `import "./x.css"`: keeps one `<style>` per stylesheet up to
+/// date (re-importing it applies an update) and exports the class map.
+pub fn js_module(css: &ProcessedCss, path: &Path) -> String {
+    let id = quote(&resolve::file_url(path));
+    let mut module = format!(
+        "const id = {id};\n\
+         let style = [...document.querySelectorAll(\"style[data-wss-css]\")].find((s) => s.dataset.wssCss === id);\n\
+         if (!style) {{\n\
+         \x20 style = document.createElement(\"style\");\n\
+         \x20 style.dataset.wssCss = id;\n\
+         \x20 document.head.append(style);\n\
+         }}\n\
+         style.textContent = {};\n",
+        quote(&css.code)
+    );
+
+    match &css.classes {
+        Some(classes) => {
+            let map = serde_json::to_string(classes).unwrap_or_else(|_| "{}".to_string());
+            module.push_str(&format!(
+                "const classes = {};\nexport default classes;\n",
+                map
+            ));
+            // Bound to temporaries so names like `class` can still be exported
+            let named: Vec<&String> = classes.keys().filter(|k| deps::is_identifier(k)).collect();
+            if !named.is_empty() {
+                let bindings: Vec<String> = named
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| format!("{}: __wss_class{}", name, i))
+                    .collect();
+                let exports: Vec<String> = named
+                    .iter()
+                    .enumerate()
+                    .map(|(i, name)| format!("__wss_class{} as {}", i, name))
+                    .collect();
+                module.push_str(&format!(
+                    "const {{ {} }} = classes;\nexport {{ {} }};\n",
+                    bindings.join(", "),
+                    exports.join(", ")
+                ));
+            }
+        }
+        None => module.push_str(&format!("export default {};\n", quote(&css.code))),
+    }
+    module
+}
+
+/// `CSS_TARGETS` (e.g. `chrome100,safari15.4`) as Lightning CSS targets;
+/// anything unrecognised (like `esnext`) means no lowering.
+fn targets() -> Targets {
+    let mut browsers = Browsers::default();
+    let mut any = false;
+
+    for target in config().css_targets.split(',') {
+        let target = target.trim().to_ascii_lowercase();
+        let Some(split) = target.find(|c: char| c.is_ascii_digit()) else {
+            continue;
+        };
+        let (name, version) = target.split_at(split);
+        let mut parts = version.split('.').map(|p| p.parse::<u32>().unwrap_or(0));
+        let version = (parts.next().unwrap_or(0) << 16) | (parts.next().unwrap_or(0) << 8);
+
+        let field = match name {
+            "chrome" => &mut browsers.chrome,
+            "edge" => &mut browsers.edge,
+            "firefox" => &mut browsers.firefox,
+            "safari" => &mut browsers.safari,
+            "ios" | "ios_saf" => &mut browsers.ios_saf,
+            "opera" => &mut browsers.opera,
+            "samsung" => &mut browsers.samsung,
+            "android" => &mut browsers.android,
+            "ie" => &mut browsers.ie,
+            _ => continue,
+        };
+        *field = Some(version);
+        any = true;
+    }
+
+    if any {
+        Targets::from(browsers)
+    } else {
+        Targets::default()
+    }
+}
+
+/// Resolves `@import`s: relative to the importing file, `/` from the project
+/// root, falling back to `node_modules` for package-style paths.
+struct ProjectProvider {
+    files: FileProvider,
+    read: Mutex<Vec<PathBuf>>,
+}
+
+impl SourceProvider for ProjectProvider {
+    type Error = io::Error;
+
+    fn read<'a>(&'a self, file: &Path) -> Result<&'a str, Self::Error> {
+        self.read.lock().unwrap().push(file.to_path_buf());
+        self.files
+            .read(file)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file.display(), e)))
+    }
+
+    fn resolve(
+        &self,
+        specifier: &str,
+        originating_file: &Path,
+    ) -> Result<ResolveResult, Self::Error> {
+        if specifier.contains("://") || specifier.starts_with("//") {
+            return Ok(ResolveResult::External(specifier.to_string()));
+        }
+        let local = local_asset(specifier, originating_file);
+        if let Some(file) = local.clone().filter(|file| file.is_file()) {
+            return Ok(file.into());
+        }
+        if !specifier.starts_with('.') && !specifier.starts_with('/') {
+            if let Some(file) = resolve::resolve_node_module(specifier, originating_file) {
+                return Ok(file.into());
+            }
+        }
+        // Let the read fail with a proper "not found" for the overlay
+        Ok(local.unwrap_or_else(|| PathBuf::from(specifier)).into())
+    }
+}
+
+/// Project file referenced by a CSS URL, relative to the file containing it.
+fn local_asset(url: &str, containing_file: &Path) -> Option<PathBuf> {
+    if url.contains("://")
+        || url.starts_with("//")
+        || url.starts_with("data:")
+        || url.starts_with('#')
+    {
+        return None;
+    }
+    let url = url.split(['?', '#']).next()?;
+    let file = match url.strip_prefix('/') {
+        Some(rest) => project_root().join(rest.strip_prefix("project/").unwrap_or(rest)),
+        None => containing_file.parent()?.join(url),
+    };
+    Some(resolve::normalize(&file))
+}
+
+fn diagnostic(path: &Path, message: String, loc: Option<ErrorLocation>) -> Diagnostic {
+    match loc {
+        Some(loc) => {
+            let file = PathBuf::from(&loc.filename);
+            let source = fs::read_to_string(&file).unwrap_or_default();
+            Diagnostic::at(
+                &file,
+                &source,
+                loc.line as usize + 1,
+                loc.column as usize,
+                message,
+            )
+        }
+        None => Diagnostic::at(path, "", 1, 1, message),
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wss-css-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn module_detection() {
+        assert!(is_module(Path::new("src/a.module.css")));
+        assert!(!is_module(Path::new("src/a.css")));
+        assert!(!is_module(Path::new("src/module.css")));
+    }
+
+    #[test]
+    fn module_class_map_includes_composes() {
+        let dir = temp_dir("classes");
+        let file = dir.join("button.module.css");
+        fs::write(
+            &file,
+            ".base { color: red }\n.primary { composes: base; composes: wide from global; }\n",
+        )
+        .unwrap();
+
+        let css = process(&file, false, &mut |_| None);
+        assert!(css.diagnostics.is_empty());
+        let classes = css.classes.as_ref().unwrap();
+        let base = &classes["base"];
+        assert_ne!(base, "base", "local names are scoped");
+        assert!(css.code.contains(&format!(".{}", base)));
+        let primary: Vec<&str> = classes["primary"].split(' ').collect();
+        assert_eq!(primary.len(), 3);
+        assert!(primary.contains(&base.as_str()));
+        assert!(primary.contains(&"wide"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn plain_stylesheets_have_no_class_map() {
+        let dir = temp_dir("plain");
+        let file = dir.join("a.css");
+        fs::write(&file, ".a { color: red }").unwrap();
+
+        let css = process(&file, false, &mut |_| None);
+        assert!(css.classes.is_none());
+        assert!(js_module(&css, &file).contains("export default \""));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn js_module_exports_identifier_classes() {
+        let css = ProcessedCss {
+            code: String::new(),
+            classes: Some(BTreeMap::from([
+                ("title".to_string(), "a_title".to_string()),
+                ("class".to_string(), "a_class".to_string()),
+                ("is-active".to_string(), "a_is-active".to_string()),
+            ])),
+            etag: String::new(),
+            diagnostics: Vec::new(),
+            files: Vec::new(),
+        };
+        let module = js_module(&css, Path::new("project/a.module.css"));
+        assert!(module.contains(
+            r#"const classes = {"class":"a_class","is-active":"a_is-active","title":"a_title"};"#
+        ));
+        assert!(module.contains("const { class: __wss_class0, title: __wss_class1 } = classes;"));
+        assert!(module.contains("export { __wss_class0 as class, __wss_class1 as title };"));
+    }
+
+    #[test]
+    fn local_asset_skips_external_urls() {
+        let css = Path::new("project/styles/a.css");
+        assert_eq!(
+            local_asset("../img/a.png?v=1#x", css),
+            Some(PathBuf::from("project/img/a.png"))
+        );
+        assert_eq!(
+            local_asset("/project/img/a.png", css),
+            Some(project_root().join("img/a.png"))
+        );
+        assert_eq!(
+            local_asset("/img/a.png", css),
+            Some(project_root().join("img/a.png"))
+        );
+        for url in [
+            "https://x/a.png",
+            "//x/a.png",
+            "data:image/png;base64,",
+            "#filter",
+        ] {
+            assert_eq!(local_asset(url, css), None, "{}", url);
+        }
+    }
+}
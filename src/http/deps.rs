@@ -0,0 +1,428 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{
+    Argument, AssignmentExpression, CallExpression, Expression, ObjectPropertyKind,
+};
+use oxc_ast_visit::{walk, Visit};
+use oxc_parser::Parser;
+use oxc_span::SourceType;
+use serde_json::Value;
+
+use super::resolve::{self, quote};
+use super::routes::project_root;
+
+const NODE_MODULES: &str = "node_modules";
+
+/// Converted modules live here, one subdirectory per lockfile hash.
+const DEPS_CACHE_DIR: &str = ".wss_deps";
+
+const LOCKFILES: &[&str] = &[
+    "package-lock.json",
+    "yarn.lock",
+    "pnpm-lock.yaml",
+    "bun.lockb",
+    "bun.lock",
+];
+
+/// How far `module.exports = require("./x")` chains are followed for names.
+const MAX_REEXPORT_DEPTH: usize = 4;
+
+lazy_static::lazy_static! {
+    /// Hash of the project's lockfile (`None` = not computed yet).
+    static ref LOCKFILE_HASH: Mutex<Option<u64>> = Mutex::new(None);
+}
+
+/// True for files inside any `node_modules` directory.
+pub fn is_dependency(path: &Path) -> bool {
+    path.components()
+        .any(|c| matches!(c, Component::Normal(name) if name == NODE_MODULES))
+}
+
+pub fn is_lockfile(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|name| LOCKFILES.contains(&name))
+}
+
+/// Forgets the lockfile hash, so converted modules are looked up (and written)
+/// under the new one.
+pub fn invalidate() {
+    *LOCKFILE_HASH.lock().unwrap() = None;
+}
+
+/// CommonJS = no `import`/`export` syntax and some use of `require`,
+/// `module.exports` or `exports`. `.cjs` files always count.
+pub fn is_commonjs(source: &str, path: &Path) -> bool {
+    if path.extension().is_some_and(|ext| ext == "cjs") {
+        return true;
+    }
+    let allocator = Allocator::default();
+    let ret = Parser::new(&allocator, source, SourceType::cjs()).parse();
+    if ret.panicked || ret.module_record.has_module_syntax {
+        return false;
+    }
+    let mut scanner = CjsScanner::default();
+    scanner.visit_program(&ret.program);
+    scanner.uses_commonjs
+}
+
+/// Returns an ES module equivalent of a CommonJS dependency, from the on-disk
+/// cache when the lockfile and file content are unchanged.
+pub fn to_esm(source: &str, path: &Path) -> String {
+    let cache_file = cache_file(source, path);
+    if let Some(code) = cache_file.as_ref().and_then(|f| fs::read_to_string(f).ok()) {
+        return code;
+    }
+
+    let code = convert(source, path);
+
+    if let Some(file) = cache_file {
+        if let Some(dir) = file.parent() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                eprintln!("[Deps] Failed to create {}: {}", dir.display(), e);
+                return code;
+            }
+        }
+        if let Err(e) = fs::write(&file, &code) {
+            eprintln!("[Deps] Failed to write {}: {}", file.display(), e);
+        }
+    }
+    code
+}
+
+/// Converts the CommonJS entry points of the project's `dependencies` ahead of
+/// the first request, so the preview doesn't wait on large packages.
+pub fn prebundle_dependencies() {
+    let root = project_root();
+    let Some(manifest) = fs::read_to_string(root.join("package.json"))
+        .ok()
+        .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+    else {
+        return;
+    };
+    let Some(dependencies) = manifest.get("dependencies").and_then(Value::as_object) else {
+        return;
+    };
+
+    let importer = root.join("package.json");
+    let mut converted = 0;
+    for name in dependencies.keys() {
+        let Some(entry) = resolve::resolve_node_module(name, &importer) else {
+            continue;
+        };
+        let Ok(source) = fs::read_to_string(&entry) else {
+            continue;
+        };
+        if is_commonjs(&source, &entry) {
+            to_esm(&source, &entry);
+            converted += 1;
+        }
+    }
+    if converted > 0 {
+        println!("[Deps] Pre-bundled {} CommonJS dependencies", converted);
+    }
+}
+
+/// Wraps the module in a CommonJS scope. Every literal `require()` becomes a
+/// hoisted `import * as`, and `require` looks the namespace up at runtime.
+fn convert(source: &str, path: &Path) -> String {
+    let scan = scan(source);
+
+    let mut out = String::new();
+    let mut table = Vec::new();
+    for (i, specifier) in scan.requires.iter().enumerate() {
+        let Some(file) = resolve_require(specifier, path) else {
+            continue;
+        };
+        out.push_str(&format!(
+            "import * as __wss_dep{} from {};\n",
+            i,
+            quote(&resolve::file_url(&file))
+        ));
+        // A JSON module's value is its default export, not the namespace
+        if file.extension().is_some_and(|ext| ext == "json") {
+            table.push(format!(
+                "{}: {{ __cjs: __wss_dep{}.default }}",
+                quote(specifier),
+                i
+            ));
+        } else {
+            table.push(format!("{}: __wss_dep{}", quote(specifier), i));
+        }
+    }
+
+    out.push_str(&format!(
+        "const __wss_requires = {{ {} }};\n\
+         const require = (id) => {{\n\
+         \x20 const ns = __wss_requires[id];\n\
+         \x20 if (!ns) throw new Error(\"Cannot require \\\"\" + id + \"\\\" from {}\");\n\
+         \x20 return \"__cjs\" in ns ? ns.__cjs : ns;\n\
+         }};\n\
+         const process = globalThis.process ?? {{ env: {{ NODE_ENV: \"development\" }} }};\n\
+         const module = {{ exports: {{}} }};\n\
+         (function (module, exports, require, process, global) {{\n",
+        table.join(", "),
+        resolve::file_url(path).replace('"', "\\\"")
+    ));
+    out.push_str(source);
+    out.push_str(
+        "\n}).call(module.exports, module, module.exports, require, process, globalThis);\n\
+         const __cjs = module.exports;\n\
+         export { __cjs };\n\
+         export default __cjs?.__esModule ? __cjs.default : __cjs;\n",
+    );
+
+    let names = export_names(&scan, path, 0);
+    if !names.is_empty() {
+        let bindings: Vec<String> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| format!("{}: __wss_export{}", name, i))
+            .collect();
+        let exports: Vec<String> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| format!("__wss_export{} as {}", i, name))
+            .collect();
+        out.push_str(&format!(
+            "const {{ {} }} = __cjs;\nexport {{ {} }};\n",
+            bindings.join(", "),
+            exports.join(", ")
+        ));
+    }
+    out
+}
+
+/// Named exports: direct assignments plus, for `module.exports = require(..)`,
+/// whatever the re-exported module assigns.
+fn export_names(scan: &CjsScanner, path: &Path, depth: usize) -> BTreeSet<String> {
+    let mut names = scan.exports.clone();
+    if depth < MAX_REEXPORT_DEPTH {
+        for specifier in &scan.reexports {
+            let Some(file) = resolve_require(specifier, path) else {
+                continue;
+            };
+            let Ok(source) = fs::read_to_string(&file) else {
+                continue;
+            };
+            names.extend(export_names(&self::scan(&source), &file, depth + 1));
+        }
+    }
+    names.retain(|name| {
+        !matches!(name.as_str(), "default" | "__esModule" | "__cjs") && is_identifier(name)
+    });
+    names
+}
+
+fn scan(source: &str) -> CjsScanner {
+    let allocator = Allocator::default();
+    let ret = Parser::new(&allocator, source, SourceType::cjs()).parse();
+    let mut scanner = CjsScanner::default();
+    if !ret.panicked {
+        scanner.visit_program(&ret.program);
+    }
+    scanner
+}
+
+fn resolve_require(specifier: &str, importer: &Path) -> Option<PathBuf> {
+    if resolve::is_bare(specifier) {
+        resolve::resolve_node_module(specifier, importer)
+    } else if specifier.starts_with('.') {
+        resolve::probe(&resolve::normalize(&importer.parent()?.join(specifier)))
+    } else {
+        None
+    }
+}
+
+/// `<project>/node_modules/.wss_deps/<lockfile hash>/<path + content hash>.js`
+fn cache_file(source: &str, path: &Path) -> Option<PathBuf> {
+    let lockfile_hash = *LOCKFILE_HASH
+        .lock()
+        .unwrap()
+        .get_or_insert_with(hash_lockfile);
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    path.hash(&mut hasher);
+    source.hash(&mut hasher);
+
+    let node_modules = project_root().join(NODE_MODULES);
+    node_modules.is_dir().then(|| {
+        node_modules
+            .join(DEPS_CACHE_DIR)
+            .join(format!("{:016x}", lockfile_hash))
+            .join(format!("{:016x}.js", hasher.finish()))
+    })
+}
+
+fn hash_lockfile() -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for name in LOCKFILES {
+        if let Ok(content) = fs::read(project_root().join(name)) {
+            name.hash(&mut hasher);
+            content.hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+#[derive(Default)]
+struct CjsScanner {
+    uses_commonjs: bool,
+    /// Literal `require("...")` specifiers, in source order
+    requires: Vec<String>,
+    /// `exports.x =`, `module.exports.x =`, `module.exports = { x }`,
+    /// `Object.defineProperty(exports, "x", ...)`
+    exports: BTreeSet<String>,
+    /// `module.exports = require("...")`
+    reexports: Vec<String>,
+}
+
+fn is_exports_object(expr: &Expression) -> bool {
+    expr.is_specific_id("exports") || expr.is_specific_member_access("module", "exports")
+}
+
+impl<'a> Visit<'a> for CjsScanner {
+    fn visit_assignment_expression(&mut self, it: &AssignmentExpression<'a>) {
+        if let Some(member) = it.left.as_member_expression() {
+            let object = member.object();
+            let property = member.static_property_name();
+
+            if is_exports_object(object) {
+                self.uses_commonjs = true;
+                if let Some(name) = property {
+                    self.exports.insert(name.to_string());
+                }
+            } else if object.is_specific_id("module") && property == Some("exports") {
+                self.uses_commonjs = true;
+                match it.right.get_inner_expression() {
+                    Expression::ObjectExpression(object) => {
+                        for property in &object.properties {
+                            if let ObjectPropertyKind::ObjectProperty(property) = property {
+                                if let Some(name) = property.key.static_name() {
+                                    self.exports.insert(name.to_string());
+                                }
+                            }
+                        }
+                    }
+                    Expression::CallExpression(call) => {
+                        if let Some(specifier) = call.common_js_require() {
+                            self.reexports.push(specifier.value.to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        walk::walk_assignment_expression(self, it);
+    }
+
+    fn visit_call_expression(&mut self, it: &CallExpression<'a>) {
+        if let Some(specifier) = it.common_js_require() {
+            self.uses_commonjs = true;
+            let specifier = specifier.value.to_string();
+            if !self.requires.contains(&specifier) {
+                self.requires.push(specifier);
+            }
+        } else if it
+            .callee
+            .is_specific_member_access("Object", "defineProperty")
+        {
+            if let [Argument::Identifier(target), Argument::StringLiteral(name), ..] =
+                it.arguments.as_slice()
+            {
+                if target.name == "exports" {
+                    self.uses_commonjs = true;
+                    self.exports.insert(name.value.to_string());
+                }
+            }
+        }
+        walk::walk_call_expression(self, it);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scans_named_exports() {
+        let scan = scan(
+            r#"
+            "use strict";
+            Object.defineProperty(exports, "__esModule", { value: true });
+            Object.defineProperty(exports, "helper", { enumerable: true, get: function () { return h; } });
+            exports.version = "1.0";
+            module.exports.render = function render() {};
+            exports["quoted"] = 1;
+            exports[dynamic] = 2;
+            "#,
+        );
+        assert!(scan.uses_commonjs);
+        assert_eq!(
+            scan.exports.iter().map(String::as_str).collect::<Vec<_>>(),
+            ["__esModule", "helper", "quoted", "render", "version"]
+        );
+    }
+
+    #[test]
+    fn scans_object_and_reexports() {
+        let scan = scan(
+            r#"
+            const a = require("./a");
+            module.exports = { a, b: 1, "c": 2, [d]: 3, ...rest };
+            "#,
+        );
+        assert_eq!(
+            scan.exports.iter().map(String::as_str).collect::<Vec<_>>(),
+            ["a", "b", "c"]
+        );
+        assert_eq!(scan.requires, ["./a"]);
+
+        let scan = self::scan(r#"module.exports = require("./lib/index.js");"#);
+        assert_eq!(scan.reexports, ["./lib/index.js"]);
+        assert!(scan.exports.is_empty());
+    }
+
+    #[test]
+    fn detects_commonjs() {
+        assert!(is_commonjs("module.exports = 1;", Path::new("a.js")));
+        assert!(is_commonjs("const x = require('x');", Path::new("a.js")));
+        assert!(!is_commonjs("export default 1;", Path::new("a.js")));
+        assert!(!is_commonjs("console.log(1);", Path::new("a.js")));
+        assert!(is_commonjs("", Path::new("a.cjs")));
+    }
+
+    #[test]
+    fn json_requires_unwrap_default() {
+        let dir = std::env::temp_dir().join(format!("wss-deps-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("data.json"), "{}").unwrap();
+        fs::write(dir.join("util.js"), "exports.x = 1;").unwrap();
+
+        let code = convert(
+            r#"module.exports = [require("./data.json"), require("./util")];"#,
+            &dir.join("index.js"),
+        );
+        assert!(
+            code.contains(r#""./data.json": { __cjs: __wss_dep0.default }"#),
+            "{}",
+            code
+        );
+        assert!(code.contains(r#""./util": __wss_dep1"#), "{}", code);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use oxc_diagnostics::{OxcDiagnostic, Severity};
+use serde::Serialize;
+
+use super::resolve;
+use crate::ws::connection::{broadcast_diagnostics, Clients};
+
+/// Lines of source shown above and below the offending line in a code frame.
+const CODE_FRAME_CONTEXT: usize = 2;
+
+/// A transpile error pinned to a source location (1-based line/column).
+#[derive(Serialize, Clone, Debug)]
+pub struct Diagnostic {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+    pub message: String,
+    pub code_frame: String,
+}
+
+lazy_static::lazy_static! {
+    /// Files whose last transpile failed, so a fix can clear the overlay.
+    static ref FAILING: Mutex<HashSet<PathBuf>> = Mutex::new(HashSet::new());
+}
+
+impl Diagnostic {
+    pub fn from_oxc(err: &OxcDiagnostic, source: &str, path: &Path) -> Self {
+        let span = err
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.first())
+            .map(|label| (label.offset(), label.len()))
+            .unwrap_or((0, 0));
+
+        let (line, column) = line_col(source, span.0);
+        let (end_line, end_column) = line_col(source, span.0 + span.1);
+
+        let mut message = err.message.to_string();
+        if let Some(help) = &err.help {
+            message.push_str(&format!("\nhelp: {}", help));
+        }
+
+        Diagnostic {
+            file: resolve::file_url(path),
+            line,
+            column,
+            end_line,
+            end_column,
+            message,
+            code_frame: code_frame(source, line, column),
+        }
+    }
+
+    /// A diagnostic at a known 1-based position, for non-oxc tools (CSS).
+    pub fn at(path: &Path, source: &str, line: usize, column: usize, message: String) -> Self {
+        Diagnostic {
+            file: resolve::file_url(path),
+            line,
+            column,
+            end_line: line,
+            end_column: column,
+            message,
+            code_frame: code_frame(source, line, column),
+        }
+    }
+}
+
+/// Converts oxc errors into diagnostics, skipping warnings and advice.
+pub fn collect(errors: &[OxcDiagnostic], source: &str, path: &Path) -> Vec<Diagnostic> {
+    errors
+        .iter()
+        .filter(|err| err.severity == Severity::Error)
+        .map(|err| Diagnostic::from_oxc(err, source, path))
+        .collect()
+}
+
+/// Module served in place of broken output: it shows the overlay (if the dev
+/// runtime is loaded) and throws, so the importer fails with a readable error.
+pub fn error_module(diagnostics: &[Diagnostic]) -> String {
+    let readable = diagnostics
+        .iter()
+        .map(|d| {
+            format!(
+                "{}:{}:{}: {}\n{}",
+                d.file, d.line, d.column, d.message, d.code_frame
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+
+    let json = serde_json::to_string(diagnostics).unwrap_or_else(|_| "[]".to_string());
+    let message = serde_json::to_string(&readable).unwrap_or_else(|_| "\"\"".to_string());
+
+    format!(
+        "const diagnostics = {json};\n\
+         globalThis.__wss_diagnostics_overlay?.(diagnostics);\n\
+         throw new Error({message});\n"
+    )
+}
+
+/// Broadcasts `diagnostics::error` for failing files and `diagnostics::clear`
+/// once a previously failing file transpiles cleanly again.
+pub fn report(clients: &Clients, path: &Path, diagnostics: &[Diagnostic]) {
+    let url = resolve::file_url(path);
+    let mut failing = FAILING.lock().unwrap();
+
+    if diagnostics.is_empty() {
+        if failing.remove(path) {
+            broadcast_diagnostics(clients, "diagnostics::clear", &url, diagnostics);
+        }
+    } else {
+        failing.insert(path.to_path_buf());
+        broadcast_diagnostics(clients, "diagnostics::error", &url, diagnostics);
+    }
+}
+
+/// 1-based line and column (in chars) for a byte offset.
+fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(source.len());
+    let before = source.get(..offset).unwrap_or(source);
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
+}
+
+fn code_frame(source: &str, line: usize, column: usize) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let first = line.saturating_sub(CODE_FRAME_CONTEXT).max(1);
+    // An error at the very end (after the last newline) still gets its line
+    let last = (line + CODE_FRAME_CONTEXT).min(lines.len().max(line));
+    let gutter = last.to_string().len();
+
+    let mut frame = String::new();
+    for n in first..=last {
+        let marker = if n == line { '>' } else { ' ' };
+        let text = lines.get(n - 1).copied().unwrap_or("");
+        frame.push_str(&format!("{} {:>gutter$} | {}\n", marker, n, text));
+        if n == line {
+            let pad = " ".repeat(column.saturating_sub(1));
+            frame.push_str(&format!("  {:>gutter$} | {}^\n", "", pad));
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_col_counts_chars() {
+        let source = "let a;\nlet é = 1;\n";
+        assert_eq!(line_col(source, 0), (1, 1));
+        assert_eq!(line_col(source, 7), (2, 1));
+        // `=` follows the two-byte `é`
+        assert_eq!(line_col(source, source.find('=').unwrap()), (2, 7));
+        assert_eq!(line_col(source, source.len()), (3, 1));
+        assert_eq!(line_col(source, source.len() + 10), (3, 1));
+    }
+
+    #[test]
+    fn code_frame_marks_line_and_column() {
+        let source = (1..=12)
+            .map(|n| format!("line{}", n))
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(
+            code_frame(&source, 10, 3),
+            "   8 | line8\n   9 | line9\n> 10 | line10\n     |   ^\n  11 | line11\n  12 | line12\n"
+        );
+        assert_eq!(
+            code_frame(&source, 1, 1),
+            "> 1 | line1\n    | ^\n  2 | line2\n  3 | line3\n"
+        );
+    }
+
+    #[test]
+    fn code_frame_at_end_of_input() {
+        let source = "f(\n";
+        let (line, column) = line_col(source, source.len());
+        assert_eq!(
+            code_frame(source, line, column),
+            "  1 | f(\n> 2 | \n    | ^\n"
+        );
+    }
+}
@@ -0,0 +1,64 @@
+use std::borrow::Cow;
+
+/// A file compiled into the binary.
+pub struct EmbeddedFile {
+    pub data: Cow<'static, [u8]>,
+    /// SHA-256 of `data`, hex; computed at build time
+    pub hash: String,
+}
+
+#[cfg(feature = "embed")]
+mod trees {
+    use rust_embed::RustEmbed;
+
+    #[derive(RustEmbed)]
+    #[folder = "web/"]
+    pub struct Web;
+
+    #[derive(RustEmbed)]
+    #[folder = "inject_scripts/"]
+    pub struct InjectScripts;
+
+    pub fn file(file: rust_embed::EmbeddedFile) -> super::EmbeddedFile {
+        let hash = file.metadata.sha256_hash();
+        super::EmbeddedFile {
+            data: file.data,
+            hash: hash.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        }
+    }
+}
+
+/// A file of the IDE (`web/`), by path relative to it.
+#[cfg(feature = "embed")]
+pub fn web(path: &str) -> Option<EmbeddedFile> {
+    trees::Web::get(path).map(trees::file)
+}
+
+/// A file of the dev runtime (`inject_scripts/`), by path relative to it.
+#[cfg(feature = "embed")]
+pub fn inject_script(path: &str) -> Option<EmbeddedFile> {
+    trees::InjectScripts::get(path).map(trees::file)
+}
+
+/// Every embedded `inject_scripts/` path, `/`-separated.
+#[cfg(feature = "embed")]
+pub fn inject_script_names() -> Vec<String> {
+    trees::InjectScripts::iter().map(Cow::into_owned).collect()
+}
+
+// Built without `embed`: `config` points both trees at the working directory
+
+#[cfg(not(feature = "embed"))]
+pub fn web(_path: &str) -> Option<EmbeddedFile> {
+    None
+}
+
+#[cfg(not(feature = "embed"))]
+pub fn inject_script(_path: &str) -> Option<EmbeddedFile> {
+    None
+}
+
+#[cfg(not(feature = "embed"))]
+pub fn inject_script_names() -> Vec<String> {
+    Vec::new()
+}
@@ -0,0 +1,29 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+lazy_static::lazy_static! {
+    /// Importer -> project files it imports, as of its last transform.
+    static ref IMPORTS: Mutex<HashMap<PathBuf, HashSet<PathBuf>>> = Mutex::new(HashMap::new());
+}
+
+/// Replaces the recorded imports of `importer`.
+pub fn set_imports(importer: &Path, imports: HashSet<PathBuf>) {
+    let mut graph = IMPORTS.lock().unwrap();
+    if imports.is_empty() {
+        graph.remove(importer);
+    } else {
+        graph.insert(importer.to_path_buf(), imports);
+    }
+}
+
+/// Modules that import `file` directly.
+pub fn importers(file: &Path) -> Vec<PathBuf> {
+    IMPORTS
+        .lock()
+        .unwrap()
+        .iter()
+        .filter(|(_, imports)| imports.contains(file))
+        .map(|(importer, _)| importer.clone())
+        .collect()
+}
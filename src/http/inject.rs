@@ -0,0 +1,233 @@
+use std::cell::Cell;
+use std::path::Path;
+
+use lol_html::html_content::ContentType;
+use lol_html::{element, rewrite_str, RewriteStrSettings};
+
+use super::routes::project_root;
+use super::{resolve, runtime, security};
+
+/// Adds the dev runtime as the first thing in `<head>` (creating the head if
+/// the page has none) and points local `<script src>`s and `<link href>`s at
+/// `/project` URLs.
+/// Falls back to the page as is if it can't be rewritten.
+pub fn inject(html: &str, page: &Path) -> String {
+    let runtime = runtime_scripts();
+    let injected = Cell::new(false);
+
+    let result = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![
+                element!("*", |el| {
+                    if injected.get() {
+                        return Ok(());
+                    }
+                    match el.tag_name().as_str() {
+                        "html" => {}
+                        "head" => {
+                            el.prepend(&runtime, ContentType::Html);
+                            injected.set(true);
+                        }
+                        // No <head>: the runtime still goes before any page content
+                        _ => {
+                            el.before(&format!("<head>{}</head>", runtime), ContentType::Html);
+                            injected.set(true);
+                        }
+                    }
+                    Ok(())
+                }),
+                element!("script[src]", |el| {
+                    if let Some(url) = el
+                        .get_attribute("src")
+                        .and_then(|src| local_url(&src, page))
+                    {
+                        el.set_attribute("src", &url)?;
+                    }
+                    Ok(())
+                }),
+                element!("link[href]", |el| {
+                    if let Some(url) = el
+                        .get_attribute("href")
+                        .and_then(|href| local_url(&href, page))
+                    {
+                        el.set_attribute("href", &url)?;
+                    }
+                    Ok(())
+                }),
+            ],
+            ..RewriteStrSettings::new()
+        },
+    );
+
+    match result {
+        Ok(out) if injected.get() => out,
+        // Empty page or only text
+        Ok(out) => format!("<head>{}</head>{}", runtime, out),
+        Err(e) => {
+            eprintln!("[HTML] Failed to rewrite {}: {}", page.display(), e);
+            html.to_string()
+        }
+    }
+}
+
+/// The IDE page, told where the isolated preview is served from.
+pub fn ide_page(html: &str, preview_origin: &str) -> String {
+    let meta = format!(
+        "\n<meta name=\"wss-preview-origin\" content=\"{}\">",
+        preview_origin.replace('"', "&quot;")
+    );
+    let result = rewrite_str(
+        html,
+        RewriteStrSettings {
+            element_content_handlers: vec![element!("head", |el| {
+                el.prepend(&meta, ContentType::Html);
+                Ok(())
+            })],
+            ..RewriteStrSettings::new()
+        },
+    );
+    result.unwrap_or_else(|e| {
+        eprintln!("[HTML] Failed to rewrite the IDE page: {}", e);
+        html.to_string()
+    })
+}
+
+/// Absolute URL for a page-relative or root-relative reference, so it still
+/// loads when the page is served from another URL (a directory index without
+/// trailing slash, an SPA fallback).
+fn local_url(src: &str, page: &Path) -> Option<String> {
+    let path = src.split(['?', '#']).next()?;
+    if path.is_empty()
+        || path.contains("://")
+        || path.starts_with("//")
+        || path.starts_with("data:")
+        || path.starts_with("/project/")
+        || path.starts_with(runtime::URL_PREFIX)
+    {
+        return None;
+    }
+    let file = match path.strip_prefix('/') {
+        Some(rest) => project_root().join(rest),
+        None => page.parent()?.join(path),
+    };
+    let file = resolve::normalize(&file);
+    if !file.starts_with(project_root()) {
+        return None;
+    }
+    Some(format!(
+        "{}{}",
+        resolve::file_url(&file),
+        &src[path.len()..]
+    ))
+}
+
+/// `<script src>` tags for the runtime, in order. The classic ones (console
+/// override) block parsing, so they run before any page script.
+/// With an isolated preview they also carry the IDE origins to `postMessage` to.
+fn runtime_scripts() -> String {
+    let targets = security::message_targets()
+        .map(|origins| format!(" data-ide-origins=\"{}\"", origins))
+        .unwrap_or_default();
+    let mut out = String::new();
+    for script in runtime::scripts().iter() {
+        let name = script.name.trim_end_matches(".js");
+        out.push_str(&format!(
+            "\n<script{} src=\"{}\" data-wss-dev=\"{}\"{}></script>",
+            if script.module {
+                " type=\"module\""
+            } else {
+                ""
+            },
+            script.url(),
+            name,
+            targets
+        ));
+    }
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_url_makes_references_absolute() {
+        let page = project_root().join("admin/index.html");
+        let url = |src| local_url(src, &page);
+        assert_eq!(url("app.js"), Some("/project/admin/app.js".into()));
+        assert_eq!(
+            url("../main.css?v=2#x"),
+            Some("/project/main.css?v=2#x".into())
+        );
+        assert_eq!(url("/src/main.ts"), Some("/project/src/main.ts".into()));
+    }
+
+    #[test]
+    fn local_url_leaves_others_alone() {
+        let page = project_root().join("index.html");
+        for src in [
+            "",
+            "?v=1",
+            "https://cdn/x.js",
+            "//cdn/x.js",
+            "data:text/javascript,1",
+            "/project/a.js",
+            "../outside.js",
+        ] {
+            assert_eq!(local_url(src, &page), None, "{}", src);
+        }
+        let runtime = format!("{}hmr.js", runtime::URL_PREFIX);
+        assert_eq!(local_url(&runtime, &page), None);
+    }
+
+    #[test]
+    fn runtime_goes_first_in_head() {
+        let runtime = runtime_scripts();
+        assert!(runtime.contains(runtime::URL_PREFIX));
+        let page = project_root().join("index.html");
+
+        let out = inject(
+            "<!doctype html><html><head><script src=\"a.js\"></script></head></html>",
+            &page,
+        );
+        assert_eq!(
+            out,
+            format!(
+                "<!doctype html><html><head>{}<script src=\"/project/a.js\"></script></head></html>",
+                runtime
+            )
+        );
+    }
+
+    #[test]
+    fn head_is_created_when_missing() {
+        let runtime = runtime_scripts();
+        let page = project_root().join("index.html");
+
+        assert_eq!(
+            inject("<html><body><p>hi</p></body></html>", &page),
+            format!(
+                "<html><head>{}</head><body><p>hi</p></body></html>",
+                runtime
+            )
+        );
+        assert_eq!(
+            inject("<p>hi</p>", &page),
+            format!("<head>{}</head><p>hi</p>", runtime)
+        );
+        assert_eq!(inject("hi", &page), format!("<head>{}</head>hi", runtime));
+    }
+
+    #[test]
+    fn links_point_at_project_urls() {
+        let page = project_root().join("docs/index.html");
+        let out = inject(
+            "<head><link rel=\"stylesheet\" href=\"site.css\"><link rel=\"icon\" href=\"https://x/i.png\"></head>",
+            &page,
+        );
+        assert!(out.contains("href=\"/project/docs/site.css\""));
+        assert!(out.contains("href=\"https://x/i.png\""));
+    }
+}
@@ -0,0 +1,185 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+use actix_web::http::header::HttpDate;
+use serde::Serialize;
+
+use super::routes::project_root;
+use crate::watcher::should_ignore_relative;
+
+#[derive(Serialize)]
+struct Listing {
+    /// URL of the directory, with a trailing slash
+    path: String,
+    parent: Option<String>,
+    entries: Vec<Entry>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    name: String,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    /// Bytes; `None` for directories
+    size: Option<u64>,
+    /// Milliseconds since the Unix epoch
+    modified: Option<u64>,
+    url: String,
+}
+
+/// Listing of a `/project` directory without an index page, as
+/// `(content type, body)`: JSON if `json`, HTML otherwise. Entries the
+/// watcher ignores (`node_modules`, editor temp files, ...) are left out.
+pub fn render(dir: &Path, json: bool) -> io::Result<(&'static str, String)> {
+    let listing = read(dir)?;
+    if json {
+        let body = serde_json::to_string(&listing).map_err(io::Error::other)?;
+        Ok(("application/json", body))
+    } else {
+        Ok(("text/html; charset=utf-8", html(&listing)))
+    }
+}
+
+fn read(dir: &Path) -> io::Result<Listing> {
+    let relative = dir.strip_prefix(project_root()).unwrap_or(dir);
+    let base = url_path(&format!("/project/{}", relative.display()));
+    let base = format!("{}/", base.trim_end_matches('/'));
+
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let path = entry.path();
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if should_ignore_relative(&path.to_string_lossy()) {
+            continue;
+        }
+        let Ok(meta) = fs::metadata(&path) else {
+            continue;
+        };
+        let is_dir = meta.is_dir();
+        entries.push(Entry {
+            url: format!("{}{}{}", base, encode(&name), if is_dir { "/" } else { "" }),
+            name,
+            kind: if is_dir { "dir" } else { "file" },
+            size: (!is_dir).then_some(meta.len()),
+            modified: meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_millis() as u64),
+        });
+    }
+    // Directories first, then by name
+    entries.sort_by(|a, b| {
+        (a.kind != "dir", a.name.to_lowercase()).cmp(&(b.kind != "dir", b.name.to_lowercase()))
+    });
+
+    let parent = (!relative.as_os_str().is_empty()).then(|| {
+        let trimmed = base.trim_end_matches('/');
+        format!("{}/", &trimmed[..trimmed.rfind('/').unwrap_or(0)])
+    });
+
+    Ok(Listing {
+        path: base,
+        parent,
+        entries,
+    })
+}
+
+fn html(listing: &Listing) -> String {
+    let mut rows = String::new();
+    if let Some(parent) = &listing.parent {
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{}\">../</a></td><td></td><td></td></tr>\n",
+            escape(parent)
+        ));
+    }
+    for entry in &listing.entries {
+        let name = if entry.kind == "dir" {
+            format!("{}/", entry.name)
+        } else {
+            entry.name.clone()
+        };
+        let modified = entry
+            .modified
+            .map(|ms| HttpDate::from(UNIX_EPOCH + Duration::from_millis(ms)).to_string())
+            .unwrap_or_default();
+        rows.push_str(&format!(
+            "<tr><td><a href=\"{}\">{}</a></td><td class=\"size\">{}</td><td>{}</td></tr>\n",
+            escape(&entry.url),
+            escape(&name),
+            entry.size.map(human_size).unwrap_or_default(),
+            modified
+        ));
+    }
+
+    let title = escape(&listing.path);
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Index of {title}</title>
+<style>
+  body {{ font: 14px/1.5 system-ui, sans-serif; margin: 2em; color: #222; }}
+  h1 {{ font-size: 1.2em; font-weight: 600; }}
+  table {{ border-collapse: collapse; min-width: 40em; }}
+  th, td {{ text-align: left; padding: 0.2em 1.5em 0.2em 0; }}
+  th {{ border-bottom: 1px solid #ddd; font-weight: 500; color: #666; }}
+  td.size {{ text-align: right; font-variant-numeric: tabular-nums; }}
+  a {{ color: #0645ad; text-decoration: none; }}
+  a:hover {{ text-decoration: underline; }}
+</style>
+</head>
+<body>
+<h1>Index of {title}</h1>
+<table>
+<tr><th>Name</th><th class="size">Size</th><th>Modified</th></tr>
+{rows}</table>
+</body>
+</html>
+"#
+    )
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// Percent-encodes one path segment.
+fn encode(segment: &str) -> String {
+    let mut out = String::new();
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=:@".contains(&byte) {
+            out.push(byte as char);
+        } else {
+            out.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    out
+}
+
+fn url_path(path: &str) -> String {
+    path.split('/').map(encode).collect::<Vec<_>>().join("/")
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -1 +1,18 @@
+pub mod assets;
+pub mod cache;
+pub mod compress;
+pub mod css;
+pub mod deps;
+pub mod diagnostics;
+pub mod embedded;
+pub mod graph;
+pub mod inject;
+pub mod listing;
+pub mod proxy;
+pub mod resolve;
 pub mod routes;
+pub mod runtime;
+pub mod security;
+pub mod sourcemap;
+pub mod transform_options;
+pub mod transpile;
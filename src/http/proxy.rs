@@ -0,0 +1,493 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use actix_http::ws::Item;
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_ws::{CloseCode, CloseReason, Message};
+use bytes::BytesMut;
+use futures_util::{SinkExt, StreamExt};
+use regex::Regex;
+use serde::Deserialize;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode as UpstreamCloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::Message as UpstreamMessage;
+
+use crate::config::config;
+
+/// Never forwarded in either direction (RFC 9110 §7.6.1), plus `host`, which
+/// the client sets from the upstream URL.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+    "host",
+];
+
+/// Handshake headers tungstenite generates itself for the upstream request.
+const WS_HANDSHAKE: &[&str] = &[
+    "sec-websocket-key",
+    "sec-websocket-version",
+    "sec-websocket-extensions",
+];
+
+/// One entry of the `PROXY_CONFIG` table:
+///
+/// ```json
+/// {
+///   "/api": "http://localhost:3000",
+///   "/auth": {
+///     "target": "http://localhost:4000",
+///     "rewrite": { "^/auth": "" },
+///     "headers": { "X-Dev-User": "alice" },
+///     "ws": false
+///   }
+/// }
+/// ```
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RuleConfig {
+    Target(String),
+    Full {
+        target: String,
+        #[serde(default)]
+        rewrite: BTreeMap<String, String>,
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+        #[serde(default = "default_ws")]
+        ws: bool,
+    },
+}
+
+fn default_ws() -> bool {
+    true
+}
+
+pub struct ProxyRule {
+    prefix: String,
+    /// Upstream base URL, without trailing slash
+    target: String,
+    rewrite: Vec<(Regex, String)>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    /// Pass WebSocket upgrades through
+    ws: bool,
+}
+
+lazy_static::lazy_static! {
+    /// Longest prefix first
+    static ref RULES: Vec<ProxyRule> = load_rules();
+    static ref CLIENT: reqwest::Client = reqwest::Client::builder()
+        // Redirects go back to the browser, like any other response
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .expect("proxy HTTP client");
+}
+
+fn load_rules() -> Vec<ProxyRule> {
+    let Some(path) = &config().proxy_config else {
+        return Vec::new();
+    };
+    let table: BTreeMap<String, RuleConfig> = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+    {
+        Ok(table) => table,
+        Err(e) => {
+            eprintln!("[Proxy] Can't load {}: {}", path.display(), e);
+            return Vec::new();
+        }
+    };
+
+    let mut rules: Vec<ProxyRule> = table
+        .into_iter()
+        .map(|(prefix, rule)| {
+            let (target, rewrite, headers, ws) = match rule {
+                RuleConfig::Target(target) => (target, BTreeMap::new(), BTreeMap::new(), true),
+                RuleConfig::Full {
+                    target,
+                    rewrite,
+                    headers,
+                    ws,
+                } => (target, rewrite, headers, ws),
+            };
+            let rewrite = rewrite
+                .into_iter()
+                .filter_map(|(pattern, replacement)| match Regex::new(&pattern) {
+                    Ok(regex) => Some((regex, replacement)),
+                    Err(e) => {
+                        eprintln!("[Proxy] {}: invalid rewrite {:?}: {}", prefix, pattern, e);
+                        None
+                    }
+                })
+                .collect();
+            let headers = headers
+                .into_iter()
+                .filter_map(|(name, value)| {
+                    let parsed = HeaderName::try_from(name.as_str())
+                        .ok()
+                        .zip(HeaderValue::try_from(value.as_str()).ok());
+                    if parsed.is_none() {
+                        eprintln!("[Proxy] {}: invalid header {}", prefix, name);
+                    }
+                    parsed
+                })
+                .collect();
+            println!("[Proxy] {} -> {}", prefix, target);
+            ProxyRule {
+                prefix,
+                target: target.trim_end_matches('/').to_string(),
+                rewrite,
+                headers,
+                ws,
+            }
+        })
+        .collect();
+    rules.sort_by_key(|rule| std::cmp::Reverse(rule.prefix.len()));
+    rules
+}
+
+/// Loads the table up front so config errors show at startup.
+pub fn init() {
+    lazy_static::initialize(&RULES);
+}
+
+fn rule_for(path: &str) -> Option<&'static ProxyRule> {
+    RULES.iter().find(|rule| prefix_matches(&rule.prefix, path))
+}
+
+/// `/api` and `/api/` both cover `/api` and `/api/...`, but not `/apix`.
+fn prefix_matches(prefix: &str, path: &str) -> bool {
+    path.strip_prefix(prefix.trim_end_matches('/'))
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+impl ProxyRule {
+    fn upstream_url(&self, req: &HttpRequest) -> String {
+        let mut path = req.path().to_string();
+        for (regex, replacement) in &self.rewrite {
+            path = regex.replace(&path, replacement.as_str()).into_owned();
+        }
+        if !path.starts_with('/') {
+            path.insert(0, '/');
+        }
+        match req.query_string() {
+            "" => format!("{}{}", self.target, path),
+            query => format!("{}{}?{}", self.target, path, query),
+        }
+    }
+}
+
+/// Default service: forwards requests matching a proxy rule, 404 otherwise.
+pub async fn handle(req: HttpRequest, payload: web::Payload) -> HttpResponse {
+    let Some(rule) = rule_for(req.path()) else {
+        return HttpResponse::NotFound().body(format!("Not found: {}", req.path()));
+    };
+
+    let is_upgrade = req
+        .headers()
+        .get(header::UPGRADE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("websocket"));
+    if is_upgrade {
+        if !rule.ws {
+            return HttpResponse::BadRequest()
+                .body("WebSocket proxying is disabled for this route");
+        }
+        return websocket(req, payload, rule).await;
+    }
+
+    let url = rule.upstream_url(&req);
+    match forward(&req, payload, rule, &url).await {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("[Proxy] {} {} -> {}: {}", req.method(), req.path(), url, e);
+            HttpResponse::BadGateway().body(format!("Proxy error for {}: {}", url, e))
+        }
+    }
+}
+
+async fn forward(
+    req: &HttpRequest,
+    mut payload: web::Payload,
+    rule: &ProxyRule,
+    url: &str,
+) -> Result<HttpResponse, Box<dyn std::error::Error>> {
+    // actix payloads aren't `Send`, so the request body is buffered
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        body.extend_from_slice(&chunk?);
+    }
+
+    let method = reqwest::Method::from_bytes(req.method().as_str().as_bytes())?;
+    let upstream = CLIENT
+        .request(method, url)
+        .headers(forwarded_headers(req, rule))
+        .body(body.freeze())
+        .send()
+        .await?;
+
+    let mut response = HttpResponse::build(StatusCode::from_u16(upstream.status().as_u16())?);
+    for (name, value) in upstream.headers() {
+        if !HOP_BY_HOP.contains(&name.as_str()) {
+            response.append_header((name.as_str(), value.as_bytes()));
+        }
+    }
+    Ok(response.streaming(upstream.bytes_stream()))
+}
+
+/// Request headers for the upstream: the client's minus hop-by-hop ones,
+/// `X-Forwarded-*`, then the rule's own headers.
+fn forwarded_headers(req: &HttpRequest, rule: &ProxyRule) -> reqwest::header::HeaderMap {
+    let mut headers = reqwest::header::HeaderMap::new();
+    copy_headers(req.headers(), &mut headers, HOP_BY_HOP);
+
+    let info = req.connection_info();
+    if let Some(peer) = info.realip_remote_addr() {
+        insert(&mut headers, "x-forwarded-for", peer);
+    }
+    insert(&mut headers, "x-forwarded-host", info.host());
+    insert(&mut headers, "x-forwarded-proto", info.scheme());
+
+    for (name, value) in &rule.headers {
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(name.as_str().as_bytes()),
+            reqwest::header::HeaderValue::from_bytes(value.as_bytes()),
+        ) {
+            headers.insert(name, value);
+        }
+    }
+    headers
+}
+
+fn copy_headers(from: &HeaderMap, to: &mut reqwest::header::HeaderMap, skip: &[&str]) {
+    for (name, value) in from {
+        if skip.contains(&name.as_str()) {
+            continue;
+        }
+        if let (Ok(name), Ok(value)) = (
+            reqwest::header::HeaderName::from_bytes(name.as_str().as_bytes()),
+            reqwest::header::HeaderValue::from_bytes(value.as_bytes()),
+        ) {
+            to.append(name, value);
+        }
+    }
+}
+
+fn insert(headers: &mut reqwest::header::HeaderMap, name: &'static str, value: &str) {
+    if let Ok(value) = reqwest::header::HeaderValue::from_str(value) {
+        headers.insert(name, value);
+    }
+}
+
+/// Connects to the upstream first (so a dead backend is a 502, not a socket
+/// that closes at once), then accepts the client and relays frames both ways.
+async fn websocket(req: HttpRequest, payload: web::Payload, rule: &ProxyRule) -> HttpResponse {
+    let url = rule.upstream_url(&req);
+    let ws_url = match url.split_once("://") {
+        Some(("https", rest)) => format!("wss://{}", rest),
+        Some(("http", rest)) => format!("ws://{}", rest),
+        _ => url.clone(),
+    };
+
+    let mut request = match ws_url.as_str().into_client_request() {
+        Ok(request) => request,
+        Err(e) => {
+            return HttpResponse::BadGateway().body(format!("Proxy error for {}: {}", ws_url, e))
+        }
+    };
+    let mut skip = HOP_BY_HOP.to_vec();
+    skip.extend_from_slice(WS_HANDSHAKE);
+    copy_headers(req.headers(), request.headers_mut(), &skip);
+    for (name, value) in forwarded_headers(&req, rule) {
+        if let Some(name) = name {
+            if !skip.contains(&name.as_str()) {
+                request.headers_mut().insert(name, value);
+            }
+        }
+    }
+
+    let (upstream, upstream_response) = match tokio_tungstenite::connect_async(request).await {
+        Ok(connected) => connected,
+        Err(e) => {
+            eprintln!("[Proxy] WS {} -> {}: {}", req.path(), ws_url, e);
+            return HttpResponse::BadGateway().body(format!("Proxy error for {}: {}", ws_url, e));
+        }
+    };
+
+    let (mut response, mut session, mut client) = match actix_ws::handle(&req, payload) {
+        Ok(accepted) => accepted,
+        Err(e) => return HttpResponse::BadRequest().body(e.to_string()),
+    };
+    // The subprotocol the upstream picked is the one the client gets
+    if let Some(protocol) = upstream_response.headers().get("sec-websocket-protocol") {
+        if let Ok(value) = HeaderValue::from_bytes(protocol.as_bytes()) {
+            response
+                .headers_mut()
+                .insert(header::SEC_WEBSOCKET_PROTOCOL, value);
+        }
+    }
+    println!("[Proxy] WS {} <-> {}", req.path(), ws_url);
+
+    let (mut upstream_tx, mut upstream_rx) = upstream.split();
+    actix_web::rt::spawn(async move {
+        let mut fragments = None;
+        loop {
+            tokio::select! {
+                msg = client.next() => {
+                    // Client gone or broken: take the upstream down with it
+                    let Some(Ok(msg)) = msg else {
+                        let _ = upstream_tx.send(UpstreamMessage::Close(None)).await;
+                        let _ = upstream_tx.close().await;
+                        break;
+                    };
+                    let forwarded = match msg {
+                        Message::Text(text) => UpstreamMessage::Text(text.to_string()),
+                        Message::Binary(bin) => UpstreamMessage::Binary(bin.to_vec()),
+                        Message::Ping(p) => UpstreamMessage::Ping(p.to_vec()),
+                        Message::Pong(p) => UpstreamMessage::Pong(p.to_vec()),
+                        Message::Close(reason) => {
+                            let frame = reason.clone().map(|r| CloseFrame {
+                                code: UpstreamCloseCode::from(u16::from(r.code)),
+                                reason: r.description.unwrap_or_default().into(),
+                            });
+                            let _ = upstream_tx.send(UpstreamMessage::Close(frame)).await;
+                            let _ = session.close(reason).await;
+                            break;
+                        }
+                        Message::Continuation(item) => match reassemble(&mut fragments, item) {
+                            Ok(Some(complete)) => complete,
+                            Ok(None) => continue,
+                            Err(problem) => {
+                                eprintln!("[Proxy] WS {}: {}", ws_url, problem);
+                                let reason = CloseReason {
+                                    code: CloseCode::Protocol,
+                                    description: Some(problem.to_string()),
+                                };
+                                let _ = session.close(Some(reason)).await;
+                                let _ = upstream_tx.close().await;
+                                break;
+                            }
+                        },
+                        Message::Nop => continue,
+                    };
+                    if upstream_tx.send(forwarded).await.is_err() {
+                        let _ = session.close(None).await;
+                        break;
+                    }
+                }
+                msg = upstream_rx.next() => {
+                    let Some(Ok(msg)) = msg else {
+                        let _ = session.close(None).await;
+                        break;
+                    };
+                    let sent = match msg {
+                        UpstreamMessage::Text(text) => session.text(text).await,
+                        UpstreamMessage::Binary(bin) => session.binary(bin).await,
+                        UpstreamMessage::Ping(p) => session.ping(&p).await,
+                        UpstreamMessage::Pong(p) => session.pong(&p).await,
+                        UpstreamMessage::Close(frame) => {
+                            let reason = frame.map(|f| CloseReason {
+                                code: CloseCode::from(u16::from(f.code)),
+                                description: Some(f.reason.into_owned()),
+                            });
+                            let _ = session.close(reason).await;
+                            break;
+                        }
+                        UpstreamMessage::Frame(_) => Ok(()),
+                    };
+                    if sent.is_err() {
+                        let _ = upstream_tx.close().await;
+                        break;
+                    }
+                }
+            }
+        }
+    });
+
+    response
+}
+
+/// Largest fragmented client message reassembled for the upstream.
+const MAX_FRAGMENTED: usize = 16 << 20;
+
+/// Collects a client message sent in fragments (actix-ws hands out the raw
+/// continuation frames); `None` until its last fragment arrives.
+fn reassemble(
+    pending: &mut Option<(bool, BytesMut)>,
+    item: Item,
+) -> Result<Option<UpstreamMessage>, &'static str> {
+    let text = matches!(item, Item::FirstText(_));
+    let last = matches!(item, Item::Last(_));
+    match item {
+        Item::FirstText(data) | Item::FirstBinary(data) if pending.is_none() => {
+            *pending = Some((text, BytesMut::from(&data[..])));
+            return Ok(None);
+        }
+        Item::FirstText(_) | Item::FirstBinary(_) => return Err("fragment out of order"),
+        Item::Continue(data) | Item::Last(data) => {
+            let Some((_, buf)) = pending.as_mut() else {
+                return Err("fragment out of order");
+            };
+            if buf.len() + data.len() > MAX_FRAGMENTED {
+                return Err("fragmented message too big");
+            }
+            buf.extend_from_slice(&data);
+        }
+    }
+    if !last {
+        return Ok(None);
+    }
+    let (text, buf) = pending.take().unwrap_or_default();
+    if text {
+        String::from_utf8(buf.to_vec())
+            .map(|text| Some(UpstreamMessage::Text(text)))
+            .map_err(|_| "invalid UTF-8 in text message")
+    } else {
+        Ok(Some(UpstreamMessage::Binary(buf.to_vec())))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    #[test]
+    fn prefix_matches_whole_segments() {
+        for prefix in ["/api", "/api/"] {
+            assert!(prefix_matches(prefix, "/api"));
+            assert!(prefix_matches(prefix, "/api/"));
+            assert!(prefix_matches(prefix, "/api/users/1"));
+            assert!(!prefix_matches(prefix, "/apix"));
+            assert!(!prefix_matches(prefix, "/api-docs"));
+            assert!(!prefix_matches(prefix, "/ap"));
+        }
+        assert!(prefix_matches("/", "/anything"));
+    }
+
+    #[test]
+    fn reassembles_fragments() {
+        let mut pending = None;
+        let first = Item::FirstText(Bytes::from_static(b"hel"));
+        assert!(reassemble(&mut pending, first).unwrap().is_none());
+        let middle = Item::Continue(Bytes::from_static(b"lo "));
+        assert!(reassemble(&mut pending, middle).unwrap().is_none());
+        let last = Item::Last(Bytes::from_static(b"world"));
+        assert_eq!(
+            reassemble(&mut pending, last).unwrap(),
+            Some(UpstreamMessage::Text("hello world".to_string()))
+        );
+        assert!(pending.is_none());
+
+        let stray = Item::Last(Bytes::from_static(b"x"));
+        assert!(reassemble(&mut pending, stray).is_err());
+    }
+}
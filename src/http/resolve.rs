@@ -0,0 +1,528 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use oxc_allocator::Allocator;
+use oxc_ast::ast::{
+    ExportAllDeclaration, ExportNamedDeclaration, ImportDeclaration, ImportExpression,
+};
+use oxc_ast_visit::{walk, Visit};
+use oxc_parser::Parser;
+use oxc_span::{GetSpan, SourceType, Span};
+use serde_json::{Map, Value};
+
+use super::routes::project_root;
+use super::{assets, graph};
+
+/// URL prefix under which files from the project's `node_modules` are served.
+pub const MODULES_URL_PREFIX: &str = "/project/@modules/";
+
+const NODE_MODULES: &str = "node_modules";
+const IMPORT_MAP_FILE: &str = "importmap.json";
+
+/// `exports` conditions we accept, in order of preference. `require` comes
+/// last: CommonJS targets are converted by the deps pre-bundler.
+const CONDITIONS: &[&str] = &["browser", "import", "module", "default", "require"];
+
+/// Suffixes tried for extensionless targets (`main: "lib/index"` etc).
+const PROBE_SUFFIXES: &[&str] = &[
+    "",
+    ".mjs",
+    ".js",
+    ".cjs",
+    ".json",
+    "/index.mjs",
+    "/index.js",
+    "/index.cjs",
+];
+
+lazy_static::lazy_static! {
+    /// Parsed `importmap.json` (`None` = not loaded yet).
+    static ref IMPORT_MAP: Mutex<Option<Map<String, Value>>> = Mutex::new(None);
+}
+
+/// Rewrites bare import specifiers (`"lodash"`, `"react/jsx-runtime"`) in a JS
+/// module to URLs the browser can load, and tags CSS/JSON/asset imports so
+/// the `/project` route serves them as modules (see `assets`). Unresolvable
+/// specifiers are left as is, so a native `<script type="importmap">` can
+/// still handle them. Local imports are recorded in the module graph.
+pub fn rewrite_imports(code: &str, importer: &Path) -> String {
+    let allocator = Allocator::default();
+    let ret = Parser::new(&allocator, code, SourceType::mjs()).parse();
+    if ret.panicked {
+        return code.to_string();
+    }
+
+    // `with { type: "json" }` imports are left to the browser
+    let mut attributed = AttributedImports::default();
+    attributed.visit_program(&ret.program);
+
+    let mut edits: Vec<(Span, String)> = Vec::new();
+    let mut imports = HashSet::new();
+
+    for (specifier, requests) in ret.module_record.requested_modules.iter() {
+        for request in requests {
+            let with_attributes = attributed.spans.contains(&request.span);
+            if let Some(url) = import_url(specifier, importer, with_attributes, &mut imports) {
+                edits.push((request.span, url));
+            }
+        }
+    }
+
+    // Only literal `import("pkg")` can be resolved ahead of time
+    for dynamic in ret.module_record.dynamic_imports.iter() {
+        let literal = dynamic.module_request.source_text(code);
+        let Some(specifier) = unquote(literal) else {
+            continue;
+        };
+        let with_attributes = attributed.spans.contains(&dynamic.module_request);
+        if let Some(url) = import_url(specifier, importer, with_attributes, &mut imports) {
+            edits.push((dynamic.module_request, url));
+        }
+    }
+
+    graph::set_imports(importer, imports);
+
+    if edits.is_empty() {
+        return code.to_string();
+    }
+
+    edits.sort_by_key(|(span, _)| std::cmp::Reverse(span.start));
+    let mut out = code.to_string();
+    for (span, url) in edits {
+        out.replace_range(span.start as usize..span.end as usize, &quote(&url));
+    }
+    out
+}
+
+/// New URL for one specifier, or `None` to leave it untouched.
+fn import_url(
+    specifier: &str,
+    importer: &Path,
+    with_attributes: bool,
+    imports: &mut HashSet<PathBuf>,
+) -> Option<String> {
+    let resolved = if is_bare(specifier) {
+        resolve_bare(specifier, importer)
+    } else {
+        None
+    };
+    let url = resolved.clone().unwrap_or_else(|| specifier.to_string());
+
+    let file = local_file(&url, importer);
+    if let Some(file) = &file {
+        imports.insert(file.clone());
+    }
+    let url = if with_attributes {
+        url
+    } else {
+        assets::tag_import(url, file.as_deref())
+    };
+    (resolved.is_some() || url != specifier).then_some(url)
+}
+
+/// Project file behind a relative or `/project/...` import URL.
+fn local_file(url: &str, importer: &Path) -> Option<PathBuf> {
+    let path = url.split(['?', '#']).next()?;
+    let file = if let Some(rest) = path.strip_prefix("/project/") {
+        module_file(rest).unwrap_or_else(|| project_root().join(rest))
+    } else if path.starts_with("./") || path.starts_with("../") {
+        importer.parent()?.join(path)
+    } else {
+        return None;
+    };
+    Some(normalize(&file))
+}
+
+/// Module request spans that carry import attributes.
+#[derive(Default)]
+struct AttributedImports {
+    spans: HashSet<Span>,
+}
+
+impl<'a> Visit<'a> for AttributedImports {
+    fn visit_import_declaration(&mut self, it: &ImportDeclaration<'a>) {
+        if it.with_clause.is_some() {
+            self.spans.insert(it.source.span);
+        }
+    }
+
+    fn visit_export_named_declaration(&mut self, it: &ExportNamedDeclaration<'a>) {
+        if let (Some(source), Some(_)) = (&it.source, &it.with_clause) {
+            self.spans.insert(source.span);
+        }
+        walk::walk_export_named_declaration(self, it);
+    }
+
+    fn visit_export_all_declaration(&mut self, it: &ExportAllDeclaration<'a>) {
+        if it.with_clause.is_some() {
+            self.spans.insert(it.source.span);
+        }
+    }
+
+    fn visit_import_expression(&mut self, it: &ImportExpression<'a>) {
+        if it.options.is_some() {
+            self.spans.insert(it.source.span());
+        }
+        walk::walk_import_expression(self, it);
+    }
+}
+
+/// Maps a `/project/{filename}` route segment under `@modules/` to a file in
+/// the project's `node_modules`.
+pub fn module_file(filename: &str) -> Option<PathBuf> {
+    let rest = filename.strip_prefix("@modules/")?;
+    // Never let `..` escape node_modules
+    if Path::new(rest)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)))
+    {
+        return None;
+    }
+    Some(project_root().join(NODE_MODULES).join(rest))
+}
+
+/// Files whose change affects how specifiers resolve.
+pub fn is_resolution_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|name| name == "package.json" || name == IMPORT_MAP_FILE)
+}
+
+pub fn invalidate() {
+    *IMPORT_MAP.lock().unwrap() = None;
+}
+
+pub fn is_bare(specifier: &str) -> bool {
+    !(specifier.starts_with('.')
+        || specifier.starts_with('/')
+        || specifier.contains("://")
+        || specifier.starts_with("data:")
+        || specifier.starts_with("blob:"))
+}
+
+/// Resolves a bare specifier to a URL: import map first, then Node-style
+/// `node_modules` lookup from the importer's directory upwards.
+pub fn resolve_bare(specifier: &str, importer: &Path) -> Option<String> {
+    if let Some(url) = resolve_import_map(specifier) {
+        return Some(url);
+    }
+    resolve_node_module(specifier, importer).map(|file| file_url(&file))
+}
+
+/// Node resolution to a file on disk.
+pub fn resolve_node_module(specifier: &str, importer: &Path) -> Option<PathBuf> {
+    let (package, subpath) = split_specifier(specifier)?;
+    let root = project_root();
+
+    let mut dir = importer.parent();
+    while let Some(current) = dir {
+        if current.file_name().is_none_or(|n| n != NODE_MODULES) {
+            let package_dir = current.join(NODE_MODULES).join(package);
+            if package_dir.is_dir() {
+                return resolve_in_package(&package_dir, subpath);
+            }
+        }
+        if current == root {
+            break;
+        }
+        dir = current.parent();
+    }
+    None
+}
+
+/// `/project/@modules/...` for node_modules files, `/project/...` otherwise.
+pub fn file_url(file: &Path) -> String {
+    let root = project_root();
+    let url = if let Ok(rest) = file.strip_prefix(root.join(NODE_MODULES)) {
+        format!("{}{}", MODULES_URL_PREFIX, rest.display())
+    } else if let Ok(rest) = file.strip_prefix(root) {
+        format!("/project/{}", rest.display())
+    } else {
+        format!("/{}", file.display())
+    };
+    url.replace('\\', "/")
+}
+
+fn resolve_import_map(specifier: &str) -> Option<String> {
+    let mut guard = IMPORT_MAP.lock().unwrap();
+    let imports = guard.get_or_insert_with(load_import_map);
+
+    if let Some(url) = imports.get(specifier).and_then(Value::as_str) {
+        return Some(url.to_string());
+    }
+
+    // Longest matching "prefix/" entry wins
+    imports
+        .iter()
+        .filter(|(key, _)| key.ends_with('/') && specifier.starts_with(key.as_str()))
+        .max_by_key(|(key, _)| key.len())
+        .and_then(|(key, value)| {
+            let base = value.as_str()?;
+            Some(format!("{}{}", base, &specifier[key.len()..]))
+        })
+}
+
+fn load_import_map() -> Map<String, Value> {
+    let path = project_root().join(IMPORT_MAP_FILE);
+    let Ok(text) = fs::read_to_string(&path) else {
+        return Map::new();
+    };
+    match serde_json::from_str::<Value>(&text) {
+        Ok(Value::Object(mut map)) => match map.remove("imports") {
+            Some(Value::Object(imports)) => imports,
+            _ => Map::new(),
+        },
+        Ok(_) => Map::new(),
+        Err(e) => {
+            eprintln!("[Resolve] Failed to parse {}: {}", path.display(), e);
+            Map::new()
+        }
+    }
+}
+
+/// `@scope/pkg/sub/path` -> (`@scope/pkg`, Some(`sub/path`))
+fn split_specifier(specifier: &str) -> Option<(&str, Option<&str>)> {
+    let split_at = if specifier.starts_with('@') {
+        let scope_end = specifier.find('/')?;
+        specifier[scope_end + 1..]
+            .find('/')
+            .map(|i| scope_end + 1 + i)
+    } else {
+        specifier.find('/')
+    };
+
+    Some(match split_at {
+        Some(i) => (&specifier[..i], Some(&specifier[i + 1..])),
+        None => (specifier, None),
+    })
+}
+
+fn resolve_in_package(package_dir: &Path, subpath: Option<&str>) -> Option<PathBuf> {
+    let manifest: Value = fs::read_to_string(package_dir.join("package.json"))
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or(Value::Null);
+
+    if let Some(exports) = manifest.get("exports") {
+        let key = subpath.map_or(".".to_string(), |s| format!("./{}", s));
+        let target = resolve_exports(exports, &key)?;
+        return probe(&package_dir.join(target.trim_start_matches("./")));
+    }
+
+    match subpath {
+        Some(subpath) => probe(&package_dir.join(subpath)),
+        None => ["module", "browser", "main"]
+            .iter()
+            .filter_map(|field| manifest.get(*field).and_then(Value::as_str))
+            .find_map(|entry| probe(&package_dir.join(entry.trim_start_matches("./"))))
+            .or_else(|| probe(&package_dir.join("index"))),
+    }
+}
+
+/// `package.json#exports` lookup for `key` (`"."` or `"./sub"`), including
+/// `"./*"` patterns and legacy `"./dir/"` folder mappings. Of several
+/// matching patterns the most specific wins, as in Node.
+fn resolve_exports(exports: &Value, key: &str) -> Option<String> {
+    let is_subpath_map = exports
+        .as_object()
+        .is_some_and(|map| map.keys().any(|k| k.starts_with('.')));
+
+    if !is_subpath_map {
+        return (key == ".").then(|| resolve_conditions(exports)).flatten();
+    }
+    let map = exports.as_object()?;
+
+    if let Some(value) = map.get(key) {
+        return resolve_conditions(value);
+    }
+
+    let (pattern, value) = map
+        .iter()
+        .filter(|(pattern, _)| match pattern.split_once('*') {
+            Some((prefix, suffix)) => {
+                !suffix.contains('*')
+                    && key.len() >= pattern.len()
+                    && key.starts_with(prefix)
+                    && key.ends_with(suffix)
+            }
+            None => pattern.ends_with('/') && key.starts_with(pattern.as_str()),
+        })
+        .min_by(|(a, _), (b, _)| pattern_key_compare(a, b))?;
+    let target = resolve_conditions(value)?;
+    Some(match pattern.split_once('*') {
+        Some((prefix, suffix)) => target.replace('*', &key[prefix.len()..key.len() - suffix.len()]),
+        None => format!("{}{}", target, &key[pattern.len()..]),
+    })
+}
+
+/// Node's `PATTERN_KEY_COMPARE`: longer text before the `*` first, then
+/// patterns before plain keys, then longer keys.
+fn pattern_key_compare(a: &str, b: &str) -> Ordering {
+    let base = |key: &str| key.find('*').map_or(key.len(), |i| i + 1);
+    base(b)
+        .cmp(&base(a))
+        .then_with(|| b.contains('*').cmp(&a.contains('*')))
+        .then_with(|| b.len().cmp(&a.len()))
+}
+
+fn resolve_conditions(value: &Value) -> Option<String> {
+    resolve_target(value).flatten()
+}
+
+/// `None` if nothing applies, `Some(None)` for a `null` target, which
+/// excludes the path instead of falling through to later conditions.
+fn resolve_target(value: &Value) -> Option<Option<String>> {
+    match value {
+        Value::String(target) => Some(Some(target.clone())),
+        Value::Null => Some(None),
+        Value::Array(targets) => {
+            let resolved: Vec<Option<String>> = targets.iter().filter_map(resolve_target).collect();
+            resolved
+                .iter()
+                .find(|target| target.is_some())
+                .or(resolved.first())
+                .cloned()
+        }
+        Value::Object(conditions) => CONDITIONS
+            .iter()
+            .filter_map(|condition| conditions.get(*condition))
+            .find_map(resolve_target),
+        _ => None,
+    }
+}
+
+/// Folds `.` and `..` so the same file always maps to the same URL. A
+/// leading `./` stays, as paths under a `PROJECT_DIR=.` root all have it.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir if out.as_os_str().is_empty() => out.push("."),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
+
+pub fn probe(path: &Path) -> Option<PathBuf> {
+    PROBE_SUFFIXES.iter().find_map(|suffix| {
+        let mut candidate = path.as_os_str().to_owned();
+        candidate.push(suffix);
+        let candidate = PathBuf::from(candidate);
+        candidate.is_file().then_some(candidate)
+    })
+}
+
+/// `text` as a JS string literal.
+pub fn quote(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_default()
+}
+
+fn unquote(literal: &str) -> Option<&str> {
+    let quote = literal
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '"' | '\'' | '`'))?;
+    let inner = literal.strip_prefix(quote)?.strip_suffix(quote)?;
+    (quote != '`' || !inner.contains("${")).then_some(inner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn splits_package_from_subpath() {
+        assert_eq!(split_specifier("react"), Some(("react", None)));
+        assert_eq!(
+            split_specifier("react-dom/client"),
+            Some(("react-dom", Some("client")))
+        );
+        assert_eq!(split_specifier("@scope/pkg"), Some(("@scope/pkg", None)));
+        assert_eq!(
+            split_specifier("@scope/pkg/a/b"),
+            Some(("@scope/pkg", Some("a/b")))
+        );
+        assert_eq!(split_specifier("@scope"), None);
+    }
+
+    #[test]
+    fn normalize_folds_dots() {
+        assert_eq!(
+            normalize(Path::new("project/a/../b/./c")),
+            Path::new("project/b/c")
+        );
+        assert_eq!(normalize(Path::new("./a/../b")), Path::new("./b"));
+        assert_eq!(normalize(Path::new("/abs/x/../y")), Path::new("/abs/y"));
+    }
+
+    #[test]
+    fn exports_conditions() {
+        let exports = json!({
+            "node": "./node.js",
+            "import": "./esm.js",
+            "require": "./cjs.js",
+        });
+        assert_eq!(resolve_exports(&exports, ".").as_deref(), Some("./esm.js"));
+        assert_eq!(resolve_exports(&exports, "./sub"), None);
+        assert_eq!(
+            resolve_exports(&json!("./main.js"), ".").as_deref(),
+            Some("./main.js")
+        );
+        let nested = json!({ ".": { "browser": { "import": "./b.mjs" }, "default": "./d.js" } });
+        assert_eq!(resolve_exports(&nested, ".").as_deref(), Some("./b.mjs"));
+        let fallback = json!({ ".": [{ "worker": "./w.js" }, "./d.js"] });
+        assert_eq!(resolve_exports(&fallback, ".").as_deref(), Some("./d.js"));
+    }
+
+    #[test]
+    fn exports_most_specific_pattern_wins() {
+        let exports = json!({
+            "./*": "./dist/*.js",
+            "./feature/*": "./dist/feature/*/index.js",
+            "./feature/*.css": "./styles/*.css",
+            "./legacy/": "./old/",
+        });
+        assert_eq!(
+            resolve_exports(&exports, "./util").as_deref(),
+            Some("./dist/util.js")
+        );
+        assert_eq!(
+            resolve_exports(&exports, "./feature/a").as_deref(),
+            Some("./dist/feature/a/index.js")
+        );
+        assert_eq!(
+            resolve_exports(&exports, "./feature/a.css").as_deref(),
+            Some("./styles/a.css")
+        );
+        assert_eq!(
+            resolve_exports(&exports, "./legacy/x.js").as_deref(),
+            Some("./old/x.js")
+        );
+        assert_eq!(pattern_key_compare("./feature/*", "./*"), Ordering::Less);
+        assert_eq!(pattern_key_compare("./a*", "./a*b"), Ordering::Greater);
+    }
+
+    #[test]
+    fn exports_null_targets_exclude() {
+        let exports = json!({
+            "./*": "./dist/*.js",
+            "./internal/*": null,
+            "./browserless": { "browser": null, "default": "./server.js" },
+        });
+        assert_eq!(resolve_exports(&exports, "./internal/x"), None);
+        assert_eq!(resolve_exports(&exports, "./browserless"), None);
+        assert_eq!(
+            resolve_exports(&exports, "./public").as_deref(),
+            Some("./dist/public.js")
+        );
+    }
+}
@@ -1,223 +1,368 @@
-use actix_web::{get, web, HttpResponse, Responder, Result};
-use mime_guess::from_path;
+use actix_web::body::MessageBody;
+use actix_web::http::header::{self, EntityTag, IfNoneMatch};
+use actix_web::http::StatusCode;
+use actix_web::{route, web, HttpMessage, HttpRequest, HttpResponse, Result};
+use bytes::Bytes;
 use std::{
+    borrow::Cow,
+    collections::hash_map::DefaultHasher,
     fs,
-    path::{Path, PathBuf},
+    hash::{Hash, Hasher},
+    io,
+    path::{Component, Path, PathBuf},
 };
 
-use oxc_allocator::Allocator;
-use oxc_codegen::{Codegen, CodegenOptions};
-use oxc_parser::Parser;
-use oxc_semantic::SemanticBuilder;
-use oxc_span::SourceType;
-use oxc_transformer::{TransformOptions, Transformer};
+use super::{
+    assets, cache, compress, css, diagnostics, embedded, inject, listing, resolve, runtime,
+    security,
+};
+use crate::config::{config, SourceMapMode};
+use crate::ws::connection::Clients;
 
-const PROJECT_ROOT: &str = "project";
-const INJECT_SCRIPTS_DIR: &str = "./inject_scripts";
+/// The directory served under `/project/` (`--project`/`PROJECT_DIR`).
+pub fn project_root() -> &'static Path {
+    &config().project_dir
+}
 
 // ================== BASIC ROUTES ==================
 
-#[get("/")]
-async fn index() -> impl Responder {
-    match fs::read_to_string("web/main.html") {
-        Ok(html) => HttpResponse::Ok().content_type("text/html").body(html),
-        Err(_) => HttpResponse::InternalServerError().body("Could not load main.html"),
+#[route("/", method = "GET", method = "HEAD")]
+async fn index(req: HttpRequest) -> Result<HttpResponse> {
+    // The IDE frames the preview from its own origin
+    if let Some(origin) = security::preview_origin() {
+        let html = match &config().web_dir {
+            Some(dir) => fs::read_to_string(dir.join("main.html"))?,
+            None => embedded::web("main.html")
+                .map(|file| String::from_utf8_lossy(&file.data).into_owned())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "main.html"))?,
+        };
+        let html = inject::ide_page(&html, origin);
+        return Ok(hashed_response(&req, "text/html; charset=utf-8", html));
     }
+    ide_file(&req, "main.html")
 }
 
-#[get("/project/{filename:.*}")]
-async fn project(filename: web::Path<String>) -> Result<HttpResponse> {
-    let mut path = PathBuf::from(PROJECT_ROOT).join(filename.as_str());
+/// The IDE's own files (`web/`), with precompressed siblings if present.
+#[route("/web/{filename:.*}", method = "GET", method = "HEAD")]
+async fn web_file(req: HttpRequest, filename: web::Path<String>) -> Result<HttpResponse> {
+    let relative = Path::new(filename.as_str());
+    if !relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)))
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    ide_file(&req, filename.as_str())
+}
+
+/// A file of `web/`: from `WEB_DIR` if set, else the copy in the binary.
+fn ide_file(req: &HttpRequest, name: &str) -> Result<HttpResponse> {
+    if let Some(dir) = &config().web_dir {
+        let path = dir.join(name);
+        if !path.is_file() {
+            return Ok(HttpResponse::NotFound().body(format!("File not found: {}", path.display())));
+        }
+        return Ok(compress::static_file(req, &path)?);
+    }
+
+    let Some(file) = embedded::web(name) else {
+        return Ok(HttpResponse::NotFound().body(format!("File not found: web/{}", name)));
+    };
+    let etag = EntityTag::new_strong(file.hash);
+    if is_not_modified(req, &etag) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish());
+    }
+    let mime = mime_guess::from_path(name).first_or_octet_stream();
+    let content_type = match (mime.type_(), mime.subtype()) {
+        (mime_guess::mime::TEXT, _) | (_, mime_guess::mime::JAVASCRIPT) => {
+            format!("{}; charset=utf-8", mime)
+        }
+        _ => mime.to_string(),
+    };
+    let body = match file.data {
+        Cow::Borrowed(bytes) => Bytes::from_static(bytes),
+        Cow::Owned(bytes) => Bytes::from(bytes),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(header::ETag(etag))
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        .body(body))
+}
+
+/// Injected dev runtime. Pages reference it with `?v=<hash>`, so a matching
+/// request can be cached for good; anything else is revalidated.
+#[route("/__wss/{name:.*}", method = "GET", method = "HEAD")]
+async fn runtime_script(req: HttpRequest, name: web::Path<String>) -> HttpResponse {
+    let Some(script) = runtime::script(&name) else {
+        return HttpResponse::NotFound().body(format!("No runtime script: {}", name));
+    };
+    let etag = EntityTag::new_strong(script.etag.clone());
+    let versioned = req
+        .query_string()
+        .split('&')
+        .any(|p| p.strip_prefix("v=") == Some(script.etag.as_str()));
+    let cache_control = if versioned {
+        vec![
+            header::CacheDirective::Public,
+            header::CacheDirective::MaxAge(31_536_000),
+            header::CacheDirective::Extension("immutable".to_string(), None),
+        ]
+    } else {
+        vec![header::CacheDirective::NoCache]
+    };
+
+    if is_not_modified(&req, &etag) {
+        return HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .insert_header(header::CacheControl(cache_control))
+            .finish();
+    }
+    HttpResponse::Ok()
+        .content_type("application/javascript")
+        .insert_header(header::ETag(etag))
+        .insert_header(header::CacheControl(cache_control))
+        .body(script.content.into_owned())
+}
+
+#[route("/project/{filename:.*}", method = "GET", method = "HEAD")]
+async fn project(
+    req: HttpRequest,
+    filename: web::Path<String>,
+    clients: web::Data<Clients>,
+) -> Result<HttpResponse> {
+    let mut path = resolve::module_file(filename.as_str())
+        .unwrap_or_else(|| project_root().join(filename.as_str()));
 
     if path.is_dir() {
         if let Some(index_path) = find_preferred_index(&path) {
             path = index_path;
+        } else if config().directory_listing {
+            let json = req
+                .headers()
+                .get(header::ACCEPT)
+                .and_then(|accept| accept.to_str().ok())
+                .is_some_and(|accept| accept.contains("application/json"));
+            let (content_type, body) = listing::render(&path, json)?;
+            return Ok(hashed_response(&req, content_type, body));
         } else {
-            return Ok(
-                HttpResponse::NotFound().body(format!("No index file in dir: {}", path.display()))
+            return not_found(
+                &req,
+                &path,
+                format!("No index file in dir: {}", path.display()),
             );
         }
     }
 
     if !path.exists() {
-        return Ok(HttpResponse::NotFound().body(format!("File not found: {}", path.display())));
+        if let Some(source_path) = transpiled_source_of_map(&path) {
+            return source_map(&req, &source_path);
+        }
+        // Deep link into a client-side routed app
+        if let Some(page) = spa_fallback(&req, filename.as_str()) {
+            return html_page(&req, &page, StatusCode::OK);
+        }
+        return not_found(&req, &path, format!("File not found: {}", path.display()));
+    }
+
+    // `?raw`, `?url` and JSON `?import` (tagged by `resolve::rewrite_imports`)
+    if let Some(module) = assets::module_for(&path, req.query_string()) {
+        return Ok(hashed_response(&req, "application/javascript", module?));
     }
 
     let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
 
     match ext {
-        "ts" | "tsx" | "jsx" => {
-            let source = fs::read_to_string(&path)?;
-            let js = transpile_ts_to_js(&source, &path)?;
-            Ok(HttpResponse::Ok()
-                .content_type("application/javascript")
-                .body(js))
-        }
-        "js" => {
-            let source = fs::read_to_string(&path)?;
-            Ok(HttpResponse::Ok()
+        "ts" | "tsx" | "jsx" | "mts" | "js" | "mjs" | "cjs" => {
+            let module = cache::transpiled(&path)?;
+            diagnostics::report(&clients, &path, &module.diagnostics);
+
+            // Compressed once per module version, not per request
+            let encoded = compress::negotiate(&req)
+                .and_then(|encoding| Some((encoding, module.compressed(encoding)?)));
+            let etag = EntityTag::new_strong(match &encoded {
+                Some((encoding, _)) => format!("{}-{}", module.etag, encoding.extension()),
+                None => module.etag.clone(),
+            });
+
+            if is_not_modified(&req, &etag) {
+                return Ok(HttpResponse::NotModified()
+                    .insert_header(header::ETag(etag))
+                    .insert_header((header::VARY, "accept-encoding"))
+                    .finish());
+            }
+
+            let mut response = HttpResponse::Ok();
+            response
                 .content_type("application/javascript")
-                .body(source))
+                .insert_header(header::ETag(etag))
+                .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+                .insert_header((header::VARY, "accept-encoding"));
+            if module.map.is_some() && config().source_maps == SourceMapMode::Sidecar {
+                // Same URL the `sourceMappingURL` comment resolves to
+                response.insert_header(("SourceMap", format!("{}.map", resolve::file_url(&path))));
+            }
+            match encoded {
+                Some((encoding, body)) => Ok(response
+                    .insert_header((header::CONTENT_ENCODING, encoding.name()))
+                    .body(body)),
+                None => Ok(response.body(module.code.clone())),
+            }
         }
-        "html" | "htm" => {
-            let content = fs::read_to_string(&path)?;
+        "css" => {
+            let processed = css::processed(&path)?;
+            diagnostics::report(&clients, &path, &processed.diagnostics);
 
-            let head_injection = inject_head_scripts();
-            let modified_content =
-                content.replace("</head>", &format!("\n{}\n</head>", head_injection));
+            // `?import` = imported from JS (rewritten by `resolve::rewrite_imports`)
+            let as_module = req.query_string().split('&').any(|p| p == "import");
+            let (body, content_type, etag) = if as_module {
+                let body = if processed.diagnostics.is_empty() {
+                    css::js_module(&processed, &path)
+                } else {
+                    diagnostics::error_module(&processed.diagnostics)
+                };
+                (
+                    body,
+                    "application/javascript",
+                    format!("{}-js", processed.etag),
+                )
+            } else {
+                (processed.code.clone(), "text/css", processed.etag.clone())
+            };
+            let etag = EntityTag::new_strong(etag);
 
+            if is_not_modified(&req, &etag) {
+                return Ok(HttpResponse::NotModified()
+                    .insert_header(header::ETag(etag))
+                    .finish());
+            }
             Ok(HttpResponse::Ok()
-                .content_type("text/html")
-                .body(modified_content))
-        }
-        _ => {
-            let content = fs::read(&path)?;
-            let mime_type = from_path(&path).first_or_text_plain();
-            Ok(HttpResponse::Ok()
-                .content_type(mime_type.as_ref())
-                .body(content))
+                .content_type(content_type)
+                .insert_header(header::ETag(etag))
+                .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+                .body(body))
         }
+        "html" | "htm" => html_page(&req, &path, StatusCode::OK),
+        // Served as is: Last-Modified/ETag revalidation and byte ranges
+        _ => Ok(compress::static_file(&req, &path)?),
     }
 }
 
-fn find_preferred_index(dir: &Path) -> Option<PathBuf> {
-    let candidates = ["main.html", "main.htm", "index.html", "index.htm"];
-
-    for candidate in candidates {
-        let mut candidate_path = dir.to_path_buf();
-        candidate_path.push(candidate);
-        if candidate_path.exists() {
-            return Some(candidate_path);
-        }
+/// `foo.tsx.map` -> `foo.tsx`, when the map belongs to a transpiled module
+fn transpiled_source_of_map(path: &Path) -> Option<PathBuf> {
+    if path.extension()? != "map" {
+        return None;
     }
-    None
+    let source = path.with_extension("");
+    let ext = source.extension()?.to_str()?;
+    (matches!(ext, "ts" | "tsx" | "jsx" | "mts") && source.exists()).then_some(source)
 }
 
-// #[get("/project/{filename:.*}")]
-// async fn project(filename: web::Path<String>) -> Result<HttpResponse> {
-//     let mut path = PathBuf::from(PROJECT_ROOT).join(filename.as_str());
-//
-//     if path.is_dir() {
-//         path.push("index.html");
-//     }
-//
-//     if !path.exists() {
-//         return Ok(HttpResponse::NotFound().body(format!("File not found: {}", path.display())));
-//     }
-//
-//     let ext = path.extension().and_then(|s| s.to_str()).unwrap_or("");
-//
-//     match ext {
-//         // ----- TS / TSX / JSX → JS via oxc -----
-//         "ts" | "tsx" | "jsx" => {
-//             let source = fs::read_to_string(&path)?;
-//             let js = transpile_ts_to_js(&source, &path)?;
-//             Ok(HttpResponse::Ok()
-//                 .content_type("application/javascript")
-//                 .body(js))
-//         }
-//         // ----- JS: serve as real JS -----
-//         "js" => {
-//             let source = fs::read_to_string(&path)?;
-//             Ok(HttpResponse::Ok()
-//                 .content_type("application/javascript")
-//                 .body(source))
-//         }
-//
-//         // ----- HTML: inject console + HMR into HEAD -----
-//         "html" => {
-//             let content = fs::read_to_string(&path)?;
-//
-//             let head_injection = inject_head_scripts();
-//             let modified_content =
-//                 content.replace("</head>", &format!("\n{}\n</head>", head_injection));
-//
-//             Ok(HttpResponse::Ok()
-//                 .content_type("text/html")
-//                 .body(modified_content))
-//         }
-//
-//         // ----- Everything else: raw file -----
-//         _ => {
-//             let content = fs::read(&path)?;
-//             let mime_type = from_path(&path).first_or_text_plain();
-//             Ok(HttpResponse::Ok()
-//                 .content_type(mime_type.as_ref())
-//                 .body(content))
-//         }
-//     }
-// }
-
-fn inject_head_scripts() -> String {
-    // lib.js (msgpack) first, then injected scripts
-    let mut out = String::new();
-    // out.push_str(r#"<script defer src="/src/lib.js"></script>"#);
-
-    if let Ok(dir) = fs::read_dir(INJECT_SCRIPTS_DIR) {
-        for entry in dir.flatten() {
-            let path = entry.path();
-            if path.extension().and_then(|e| e.to_str()) == Some("js") {
-                if let Ok(content) = fs::read_to_string(&path) {
-                    out.push_str("\n<script type=\"module\">\n");
-                    out.push_str(&content);
-                    out.push_str("\n</script>");
-                }
-            }
+fn source_map(req: &HttpRequest, source_path: &Path) -> Result<HttpResponse> {
+    let module = cache::transpiled(source_path)?;
+    match &module.map {
+        Some(map) => Ok(hashed_response(req, "application/json", map.clone())),
+        None => {
+            Ok(HttpResponse::NotFound()
+                .body(format!("No source map for: {}", source_path.display())))
         }
     }
-
-    out
 }
 
-fn transpile_ts_to_js(source: &str, path: &PathBuf) -> Result<String> {
-    let allocator = Allocator::default();
-    let source_type = SourceType::from_path(path).unwrap_or_else(|_| SourceType::ts());
+/// A project page with the dev runtime injected.
+fn html_page(req: &HttpRequest, page: &Path, status: StatusCode) -> Result<HttpResponse> {
+    let content = fs::read_to_string(page)?;
+    let html = inject::inject(&content, page);
+    if status == StatusCode::OK {
+        return Ok(hashed_response(req, "text/html; charset=utf-8", html));
+    }
+    Ok(HttpResponse::build(status)
+        .content_type("text/html; charset=utf-8")
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        .body(html))
+}
 
-    // 1. Parse
-    let parse_ret = Parser::new(&allocator, source, source_type).parse();
+/// The project's nearest `404.html` (searching up from `path`, like static
+/// hosts do), else a plain-text 404.
+fn not_found(req: &HttpRequest, path: &Path, message: String) -> Result<HttpResponse> {
+    let page = path
+        .ancestors()
+        .skip(1)
+        .take_while(|dir| dir.starts_with(project_root()))
+        .map(|dir| dir.join("404.html"))
+        .find(|page| page.is_file());
+    match page {
+        Some(page) => html_page(req, &page, StatusCode::NOT_FOUND),
+        None => Ok(HttpResponse::NotFound().body(message)),
+    }
+}
 
-    if !parse_ret.errors.is_empty() {
-        eprintln!("[OXC] Parse errors in {}:", path.display());
-        for err in &parse_ret.errors {
-            eprintln!("{:?}", err);
-        }
+/// Page an `SPA_FALLBACK` rule serves for a missing path (longest matching
+/// prefix wins). Only for navigations, so missing modules and assets still 404.
+fn spa_fallback(req: &HttpRequest, filename: &str) -> Option<PathBuf> {
+    let accepts_html = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if !accepts_html {
+        return None;
     }
 
-    let mut program = parse_ret.program;
+    let rule = config()
+        .spa_fallback
+        .iter()
+        .filter(|rule| filename.starts_with(&rule.prefix))
+        .max_by_key(|rule| rule.prefix.len())?;
+    let root = project_root();
+    match &rule.page {
+        Some(page) => Some(root.join(page)).filter(|page| page.is_file()),
+        None => find_preferred_index(&root.join(&rule.prefix)),
+    }
+}
 
-    // 2. Semantic info (needed by transformer)
-    let semantic_ret = SemanticBuilder::new()
-        .with_excess_capacity(2.0)
-        .build(&program);
+/// Response for generated content, revalidated by a strong ETag hashed from
+/// the body.
+fn hashed_response<B>(req: &HttpRequest, content_type: &str, body: B) -> HttpResponse
+where
+    B: AsRef<[u8]> + MessageBody + 'static,
+{
+    let mut hasher = DefaultHasher::new();
+    body.as_ref().hash(&mut hasher);
+    let etag = EntityTag::new_strong(format!("{:016x}", hasher.finish()));
 
-    if !semantic_ret.errors.is_empty() {
-        eprintln!("[OXC] Semantic errors in {}:", path.display());
-        for err in &semantic_ret.errors {
-            eprintln!("{:?}", err);
-        }
+    if is_not_modified(req, &etag) {
+        return HttpResponse::NotModified()
+            .insert_header(header::ETag(etag))
+            .finish();
     }
+    HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(header::ETag(etag))
+        .insert_header(header::CacheControl(vec![header::CacheDirective::NoCache]))
+        .body(body)
+}
 
-    let scoping = semantic_ret.semantic.into_scoping();
-
-    // 3. Transform (enable TS/JSX; target esnext by default)
-    let options = TransformOptions::enable_all();
-    // you can tweak options.typescript / jsx / env here if you want
+/// `If-None-Match` check for conditional GETs
+fn is_not_modified(req: &HttpRequest, etag: &EntityTag) -> bool {
+    match req.get_header::<IfNoneMatch>() {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        None => false,
+    }
+}
 
-    let transform_ret = Transformer::new(&allocator, path.as_path(), &options)
-        .build_with_scoping(scoping, &mut program);
+pub fn find_preferred_index(dir: &Path) -> Option<PathBuf> {
+    let candidates = ["main.html", "main.htm", "index.html", "index.htm"];
 
-    if !transform_ret.errors.is_empty() {
-        eprintln!("[OXC] Transform errors in {}:", path.display());
-        for err in &transform_ret.errors {
-            eprintln!("{:?}", err);
+    for candidate in candidates {
+        let mut candidate_path = dir.to_path_buf();
+        candidate_path.push(candidate);
+        if candidate_path.exists() {
+            return Some(candidate_path);
         }
     }
-
-    // 4. Emit JS
-    let js = Codegen::new()
-        .with_options(CodegenOptions::default())
-        .build(&program)
-        .code;
-
-    Ok(js)
+    None
 }
@@ -0,0 +1,237 @@
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+use serde::Deserialize;
+
+use super::embedded;
+use crate::config::config;
+
+/// URL prefix the runtime scripts are served under.
+pub const URL_PREFIX: &str = "/__wss/";
+
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// Libraries the runtime imports; served but never injected into pages.
+const VENDOR_DIR: &str = "vendor/";
+
+/// One runtime script as served under `/__wss/`.
+#[derive(Clone)]
+pub struct RuntimeScript {
+    pub name: String,
+    pub content: Cow<'static, str>,
+    /// Hash of `content`; also the `?v=` of the injected URL
+    pub etag: String,
+    /// Classic scripts run before the page's own, modules after parsing
+    pub module: bool,
+}
+
+impl RuntimeScript {
+    pub fn url(&self) -> String {
+        format!("{}{}?v={}", URL_PREFIX, self.name, self.etag)
+    }
+}
+
+/// `manifest.json`: injection order, and which scripts must run as classic
+/// scripts (before anything else on the page).
+#[derive(Deserialize, Default)]
+struct Manifest {
+    #[serde(default)]
+    scripts: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize)]
+struct ManifestEntry {
+    file: String,
+    #[serde(default = "default_module")]
+    module: bool,
+}
+
+fn default_module() -> bool {
+    true
+}
+
+lazy_static::lazy_static! {
+    static ref EMBEDDED_SCRIPTS: Vec<RuntimeScript> = ordered_scripts(&EmbeddedSource);
+}
+
+/// Runtime scripts in injection order: manifest entries first, then any other
+/// `.js` file as a module, alphabetically.
+pub fn scripts() -> Cow<'static, [RuntimeScript]> {
+    match &config().inject_scripts_dir {
+        Some(dir) => Cow::Owned(ordered_scripts(&DirSource(dir))),
+        None => Cow::Borrowed(EMBEDDED_SCRIPTS.as_slice()),
+    }
+}
+
+/// A runtime script or `vendor/` library by path below `/__wss/`.
+pub fn script(name: &str) -> Option<RuntimeScript> {
+    if let Some(script) = scripts().iter().find(|script| script.name == name) {
+        return Some(script.clone());
+    }
+    let library = name.strip_prefix(VENDOR_DIR)?;
+    if library.is_empty() || library.contains(['/', '\\']) || library.starts_with('.') {
+        return None;
+    }
+    let content = match &config().inject_scripts_dir {
+        Some(dir) => DirSource(dir).read(name)?,
+        None => EmbeddedSource.read(name)?,
+    };
+    Some(RuntimeScript {
+        name: name.to_string(),
+        etag: hash(&content),
+        content,
+        module: true,
+    })
+}
+
+trait Source {
+    fn names(&self) -> Vec<String>;
+    fn read(&self, name: &str) -> Option<Cow<'static, str>>;
+}
+
+/// `inject_scripts/` as of the build. `INJECT_SCRIPTS_DIR` replaces it at
+/// runtime, so the scripts can be edited without rebuilding.
+struct EmbeddedSource;
+
+impl Source for EmbeddedSource {
+    fn names(&self) -> Vec<String> {
+        embedded::inject_script_names()
+    }
+
+    fn read(&self, name: &str) -> Option<Cow<'static, str>> {
+        match embedded::inject_script(name)?.data {
+            Cow::Borrowed(bytes) => std::str::from_utf8(bytes).ok().map(Cow::Borrowed),
+            Cow::Owned(bytes) => String::from_utf8(bytes).ok().map(Cow::Owned),
+        }
+    }
+}
+
+struct DirSource<'a>(&'a Path);
+
+impl Source for DirSource<'_> {
+    fn names(&self) -> Vec<String> {
+        fs::read_dir(self.0)
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .filter_map(|entry| entry.file_name().into_string().ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn read(&self, name: &str) -> Option<Cow<'static, str>> {
+        fs::read_to_string(self.0.join(name)).ok().map(Cow::Owned)
+    }
+}
+
+fn ordered_scripts(source: &dyn Source) -> Vec<RuntimeScript> {
+    let manifest: Manifest = source
+        .read(MANIFEST_FILE)
+        .and_then(|text| {
+            serde_json::from_str(&text)
+                .map_err(|e| eprintln!("[Runtime] Invalid {}: {}", MANIFEST_FILE, e))
+                .ok()
+        })
+        .unwrap_or_default();
+
+    let mut order: Vec<(String, bool)> = manifest
+        .scripts
+        .into_iter()
+        .map(|entry| (entry.file, entry.module))
+        .collect();
+    let mut others: Vec<String> = source
+        .names()
+        .into_iter()
+        .filter(|name| {
+            name.ends_with(".js")
+                && !name.starts_with(VENDOR_DIR)
+                && !order.iter().any(|(listed, _)| listed == name)
+        })
+        .collect();
+    others.sort();
+    order.extend(others.into_iter().map(|name| (name, true)));
+
+    order
+        .into_iter()
+        .filter_map(|(name, module)| {
+            let Some(content) = source.read(&name) else {
+                eprintln!("[Runtime] Missing inject script: {}", name);
+                return None;
+            };
+            Some(RuntimeScript {
+                etag: hash(&content),
+                name,
+                content,
+                module,
+            })
+        })
+        .collect()
+}
+
+fn hash(content: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    content.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_order_then_the_rest() {
+        let dir = std::env::temp_dir().join(format!("wss-runtime-{}", std::process::id()));
+        fs::create_dir_all(dir.join("vendor")).unwrap();
+        for name in [
+            "b.js",
+            "a.js",
+            "console.js",
+            "hmr.js",
+            "notes.txt",
+            "vendor/lib.js",
+        ] {
+            fs::write(dir.join(name), name).unwrap();
+        }
+        fs::write(
+            dir.join(MANIFEST_FILE),
+            r#"{ "scripts": [
+                { "file": "console.js", "module": false },
+                { "file": "hmr.js" },
+                { "file": "missing.js" }
+            ] }"#,
+        )
+        .unwrap();
+
+        let scripts: Vec<(String, bool)> = ordered_scripts(&DirSource(&dir))
+            .into_iter()
+            .map(|script| (script.name, script.module))
+            .collect();
+        assert_eq!(
+            scripts,
+            [
+                ("console.js".to_string(), false),
+                ("hmr.js".to_string(), true),
+                ("a.js".to_string(), true),
+                ("b.js".to_string(), true),
+            ]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn embedded_runtime_is_ordered() {
+        let scripts = ordered_scripts(&EmbeddedSource);
+        assert!(!scripts.is_empty());
+        // Classic scripts come first so they run before the page's own
+        let first_module = scripts
+            .iter()
+            .position(|s| s.module)
+            .unwrap_or(scripts.len());
+        assert!(scripts[first_module..].iter().all(|s| s.module));
+        assert!(scripts.iter().all(|s| !s.name.starts_with(VENDOR_DIR)));
+    }
+}
@@ -0,0 +1,306 @@
+use std::net::IpAddr;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpRequest, HttpResponse};
+use reqwest::Url;
+
+use crate::config::config;
+
+/// Which page a request belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    /// The IDE itself (`/`, `/web/`), holder of the shell-capable WebSocket
+    Ide,
+    /// Project code (`/project/`, `/__wss/`, proxied backends)
+    Preview,
+}
+
+/// `PREVIEW_ORIGIN`, if the preview is isolated from the IDE.
+pub fn preview_origin() -> Option<&'static str> {
+    config().preview_origin.as_deref()
+}
+
+/// Port the server must also listen on for `PREVIEW_ORIGIN`, if that isn't
+/// `port` already (a subdomain-style origin on the same port needs none).
+pub fn preview_port(port: u16) -> Option<u16> {
+    let url = Url::parse(preview_origin()?).ok()?;
+    url.port_or_known_default()
+        .filter(|preview| *preview != port)
+}
+
+/// Side of a request: by `Host` when the preview has its own origin, else by
+/// path (both share one origin, so the split is only nominal).
+pub fn side(req: &HttpRequest) -> Side {
+    match preview_origin() {
+        Some(origin) => {
+            if authority(origin) == host(req) {
+                Side::Preview
+            } else {
+                Side::Ide
+            }
+        }
+        None if is_ide_path(req.path()) => Side::Ide,
+        None => Side::Preview,
+    }
+}
+
+fn is_ide_path(path: &str) -> bool {
+    path == "/" || path.starts_with("/web/")
+}
+
+/// `host[:port]` of an origin
+fn authority(origin: &str) -> Option<&str> {
+    origin.split_once("://").map(|(_, authority)| authority)
+}
+
+/// `host` of `host[:port]`; IPv6 addresses keep their brackets.
+fn hostname(authority: &str) -> &str {
+    match authority.rfind(':') {
+        Some(i) if !authority[i..].contains(']') => &authority[..i],
+        _ => authority,
+    }
+}
+
+/// `Host` as the client sent it. `connection_info()` would prefer
+/// `X-Forwarded-Host`, which a page can set on its own requests.
+fn host(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .or_else(|| req.uri().authority().map(|authority| authority.as_str()))
+}
+
+/// Whether the request names this server. After DNS rebinding a page on any
+/// domain can reach localhost as its own origin, so only names nobody else
+/// can point here are served: `localhost`, IP addresses and the hosts of
+/// `PREVIEW_ORIGIN` and `IDE_ORIGIN`. Clients without a `Host` aren't browsers.
+pub fn allowed_host(req: &HttpRequest) -> bool {
+    host(req).is_none_or(is_allowed_host)
+}
+
+fn is_allowed_host(host: &str) -> bool {
+    let name = hostname(host);
+    name.eq_ignore_ascii_case("localhost")
+        || name
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<IpAddr>()
+            .is_ok()
+        || preview_origin()
+            .into_iter()
+            .chain(config().ide_origins.iter().map(String::as_str))
+            .filter_map(authority)
+            .any(|origin| hostname(origin).eq_ignore_ascii_case(name))
+}
+
+/// Who opened a WebSocket, judged by its `Origin` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peer {
+    Ide,
+    /// Previewed code: gets HMR and remapping, but no commands
+    Preview,
+}
+
+impl Peer {
+    /// Whether a WebSocket message of `msg_type` is accepted from this peer.
+    /// Previewed code may only report (console, eval results), describe
+    /// itself, remap stacks and use pub/sub; anything new stays IDE-only
+    /// until it's added here.
+    pub fn may_send(self, msg_type: &str) -> bool {
+        self == Peer::Ide
+            || msg_type.starts_with("console::")
+            || matches!(
+                msg_type,
+                "eval::result"
+                    | "client::hello"
+                    | "stack::remap"
+                    | "subscribe"
+                    | "unsubscribe"
+                    | "publish"
+            )
+    }
+}
+
+/// `None` for pages of any other origin or an unknown `Host`; browsers let
+/// any site open a WebSocket to localhost, so those are refused outright.
+/// Clients without an `Origin` aren't browsers and count as the IDE.
+pub fn ws_peer(req: &HttpRequest) -> Option<Peer> {
+    if !allowed_host(req) {
+        return None;
+    }
+    let origin = req
+        .headers()
+        .get(header::ORIGIN)
+        .and_then(|value| value.to_str().ok())
+        .map(|origin| origin.trim_end_matches('/'));
+
+    let from_preview = preview_origin().is_some_and(|preview| origin == Some(preview));
+    if from_preview || (preview_origin().is_some() && side(req) == Side::Preview) {
+        return Some(Peer::Preview);
+    }
+    match origin {
+        None => Some(Peer::Ide),
+        Some(origin)
+            if authority(origin) == host(req)
+                || config().ide_origins.iter().any(|ide| ide == origin) =>
+        {
+            Some(Peer::Ide)
+        }
+        Some(_) => None,
+    }
+}
+
+/// Refuses unknown hosts, keeps each side to its own origin and adds the
+/// security headers. With `PREVIEW_ORIGIN` set, the IDE isn't served there
+/// and `/project` on the IDE origin redirects to it.
+pub async fn headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, Error> {
+    if !allowed_host(req.request()) {
+        return Ok(req.into_response(
+            HttpResponse::Forbidden().body("Unknown host; add its origin to IDE_ORIGIN"),
+        ));
+    }
+    let side = side(req.request());
+    if let Some(origin) = preview_origin() {
+        let path = req.path();
+        if side == Side::Preview && is_ide_path(path) {
+            return Ok(req.into_response(HttpResponse::NotFound().finish()));
+        }
+        if side == Side::Ide && (path.starts_with("/project/") || path == "/project") {
+            let target = match req.query_string() {
+                "" => format!("{}{}", origin, path),
+                query => format!("{}{}?{}", origin, path, query),
+            };
+            return Ok(req.into_response(
+                HttpResponse::TemporaryRedirect()
+                    .insert_header((header::LOCATION, target))
+                    .finish(),
+            ));
+        }
+    }
+
+    let mut response = next.call(req).await?;
+    apply(response.headers_mut(), side);
+    Ok(response.map_into_boxed_body())
+}
+
+/// Headers a response doesn't already set itself (proxied backends may).
+fn apply(headers: &mut HeaderMap, side: Side) {
+    let settings = config();
+    let mut set = |name: HeaderName, value: String| {
+        if !headers.contains_key(&name) {
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert(name, value);
+            }
+        }
+    };
+
+    set(header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string());
+    let csp = match side {
+        // The IDE runs shell commands: nobody gets to frame it
+        Side::Ide => "frame-ancestors 'self'".to_string(),
+        Side::Preview => {
+            let mut csp = String::from("frame-ancestors 'self'");
+            if preview_origin().is_some() {
+                for origin in &settings.ide_origins {
+                    csp.push(' ');
+                    csp.push_str(origin);
+                }
+            }
+            if let Some(extra) = &settings.preview_csp {
+                csp.push_str("; ");
+                csp.push_str(extra);
+            }
+            csp
+        }
+    };
+    set(header::CONTENT_SECURITY_POLICY, csp);
+
+    if settings.cross_origin_isolation {
+        set(
+            header::CROSS_ORIGIN_OPENER_POLICY,
+            "same-origin".to_string(),
+        );
+        set(
+            header::CROSS_ORIGIN_EMBEDDER_POLICY,
+            "require-corp".to_string(),
+        );
+        // A cross-origin preview frame must opt in to being embedded
+        let resource_policy = match side {
+            Side::Preview if preview_origin().is_some() => "cross-origin",
+            _ => "same-origin",
+        };
+        set(
+            header::CROSS_ORIGIN_RESOURCE_POLICY,
+            resource_policy.to_string(),
+        );
+    }
+}
+
+/// `data-ide-origins` for the injected runtime: where the preview may
+/// `postMessage` to; `None` when the IDE shares the preview's origin.
+pub fn message_targets() -> Option<String> {
+    preview_origin()?;
+    Some(config().ide_origins.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn hostname_drops_the_port() {
+        assert_eq!(hostname("localhost:8080"), "localhost");
+        assert_eq!(hostname("localhost"), "localhost");
+        assert_eq!(hostname("[::1]:8080"), "[::1]");
+        assert_eq!(hostname("[::1]"), "[::1]");
+    }
+
+    #[test]
+    fn loopback_and_addresses_are_allowed() {
+        for host in [
+            "localhost:8080",
+            "LOCALHOST",
+            "127.0.0.1:8080",
+            "[::1]:8080",
+            "192.168.1.5:8080",
+        ] {
+            assert!(is_allowed_host(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn other_names_are_refused() {
+        for host in [
+            "evil.example:8080",
+            "localhost.evil.example",
+            "127.0.0.1.nip.io:8080",
+            "",
+        ] {
+            assert!(!is_allowed_host(host), "{}", host);
+        }
+    }
+
+    #[test]
+    fn rebound_websockets_are_refused() {
+        let req = TestRequest::default()
+            .insert_header((header::HOST, "evil.example:8080"))
+            .insert_header(("x-forwarded-host", "localhost:8080"))
+            .insert_header((header::ORIGIN, "http://evil.example:8080"))
+            .to_http_request();
+        assert!(!allowed_host(&req));
+        assert_eq!(ws_peer(&req), None);
+
+        let req = TestRequest::default()
+            .insert_header((header::HOST, "localhost:8080"))
+            .insert_header((header::ORIGIN, "http://localhost:8080"))
+            .to_http_request();
+        assert_eq!(ws_peer(&req), Some(Peer::Ide));
+    }
+}
@@ -0,0 +1,50 @@
+use oxc_sourcemap::SourceMap;
+use regex::{Captures, Regex};
+
+use super::cache;
+use super::routes::project_root;
+
+lazy_static::lazy_static! {
+    /// `http://host/project/a.tsx?t=1:10:5` (Chrome) or `/project/a.tsx:10:5` (Firefox)
+    static ref STACK_LOCATION: Regex = Regex::new(
+        r"(?P<origin>https?://[^/\s()]+)?/(?P<path>project/[^\s():?#]+)(?:\?[^\s():]*)?:(?P<line>\d+):(?P<col>\d+)"
+    )
+    .unwrap();
+}
+
+/// Rewrites every `/project/...:line:col` location in a stack trace to its
+/// original TS/TSX position, leaving locations without a source map untouched.
+pub fn remap_stack(stack: &str) -> String {
+    STACK_LOCATION
+        .replace_all(stack, |caps: &Captures| {
+            remap_location(caps).unwrap_or_else(|| caps[0].to_string())
+        })
+        .into_owned()
+}
+
+fn remap_location(caps: &Captures) -> Option<String> {
+    let relative = caps["path"].strip_prefix("project/")?;
+    let path = project_root().join(relative);
+    let ext = path.extension()?.to_str()?;
+    if !matches!(ext, "ts" | "tsx" | "jsx" | "mts") {
+        return None;
+    }
+
+    let line: u32 = caps["line"].parse().ok()?;
+    let col: u32 = caps["col"].parse().ok()?;
+
+    let module = cache::transpiled(&path).ok()?;
+    let json = std::str::from_utf8(module.map.as_ref()?).ok()?;
+    let map = SourceMap::from_json_string(json).ok()?;
+    let table = map.generate_lookup_table();
+    // Stack traces are 1-based, source maps 0-based
+    let token = map.lookup_token(&table, line.checked_sub(1)?, col.saturating_sub(1))?;
+
+    Some(format!(
+        "{}/{}:{}:{}",
+        caps.name("origin").map_or("", |m| m.as_str()),
+        &caps["path"],
+        token.get_src_line() + 1,
+        token.get_src_col() + 1
+    ))
+}
@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use oxc_transformer::{DecoratorOptions, EnvOptions, JsxOptions, JsxRuntime, TransformOptions};
+use serde_json::Value;
+
+use super::routes::project_root;
+use crate::config::config;
+
+const CONFIG_FILES: &[&str] = &["tsconfig.json", "jsconfig.json"];
+
+/// Guards against `extends` cycles.
+const MAX_EXTENDS_DEPTH: usize = 8;
+
+/// How JSX is compiled, mirroring tsconfig's `compilerOptions.jsx`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum JsxMode {
+    /// `react`: classic `React.createElement` calls (or `jsxFactory`)
+    Classic,
+    /// `react-jsx`: automatic runtime imported from `jsxImportSource`
+    Automatic,
+    /// `react-jsxdev`: automatic runtime with source locations
+    AutomaticDev,
+    /// `preserve`: JSX is left untouched
+    Preserve,
+}
+
+impl JsxMode {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "react" => Some(JsxMode::Classic),
+            "react-jsx" => Some(JsxMode::Automatic),
+            "react-jsxdev" => Some(JsxMode::AutomaticDev),
+            "preserve" | "react-native" => Some(JsxMode::Preserve),
+            _ => None,
+        }
+    }
+}
+
+/// Transform settings for one file: server defaults (`JSX`, `JSX_IMPORT_SOURCE`,
+/// `TRANSFORM_TARGET`, `EXPERIMENTAL_DECORATORS`) overridden by the nearest
+/// `tsconfig.json`/`jsconfig.json` (and whatever it `extends`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TransformSettings {
+    pub jsx: JsxMode,
+    pub jsx_import_source: Option<String>,
+    pub jsx_factory: Option<String>,
+    pub jsx_fragment_factory: Option<String>,
+    pub experimental_decorators: bool,
+    pub emit_decorator_metadata: bool,
+    /// esbuild-style target list, e.g. `esnext` or `es2020,chrome80`
+    pub target: String,
+}
+
+lazy_static::lazy_static! {
+    /// Resolved settings per directory, cleared whenever a config file changes.
+    static ref DIR_SETTINGS: Mutex<HashMap<PathBuf, TransformSettings>> =
+        Mutex::new(HashMap::new());
+}
+
+impl TransformSettings {
+    pub fn from_env() -> Self {
+        TransformSettings {
+            jsx: env::var("JSX")
+                .ok()
+                .and_then(|v| JsxMode::parse(&v))
+                .unwrap_or(JsxMode::AutomaticDev),
+            jsx_import_source: env::var("JSX_IMPORT_SOURCE").ok(),
+            jsx_factory: None,
+            jsx_fragment_factory: None,
+            experimental_decorators: env::var("EXPERIMENTAL_DECORATORS")
+                .is_ok_and(|v| v == "1" || v == "true"),
+            emit_decorator_metadata: false,
+            target: env::var("TRANSFORM_TARGET").unwrap_or_else(|_| "esnext".to_string()),
+        }
+    }
+
+    /// Settings for a file under the project root.
+    pub fn for_file(path: &Path) -> Self {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        if let Some(settings) = DIR_SETTINGS.lock().unwrap().get(dir) {
+            return settings.clone();
+        }
+
+        let mut settings = config().transform.clone();
+        // Outermost config first, so nested ones override it
+        for config_file in config_files_above(dir, project_root()).iter().rev() {
+            settings.apply_config_file(config_file, 0);
+        }
+
+        DIR_SETTINGS
+            .lock()
+            .unwrap()
+            .insert(dir.to_path_buf(), settings.clone());
+        settings
+    }
+
+    pub fn to_oxc(&self) -> TransformOptions {
+        let env = EnvOptions::from_target(&self.target).unwrap_or_else(|e| {
+            eprintln!("[OXC] Invalid target {:?}: {}", self.target, e);
+            EnvOptions::default()
+        });
+
+        let mut jsx = match self.jsx {
+            JsxMode::Preserve => JsxOptions::disable(),
+            _ => JsxOptions::enable(),
+        };
+        match self.jsx {
+            JsxMode::Classic => {
+                jsx.runtime = JsxRuntime::Classic;
+                jsx.pragma = self.jsx_factory.clone();
+                jsx.pragma_frag = self.jsx_fragment_factory.clone();
+            }
+            JsxMode::Automatic | JsxMode::AutomaticDev => {
+                jsx.runtime = JsxRuntime::Automatic;
+                jsx.import_source = self.jsx_import_source.clone();
+                jsx.development = self.jsx == JsxMode::AutomaticDev;
+            }
+            JsxMode::Preserve => {}
+        }
+        jsx.conform();
+
+        let mut options = TransformOptions {
+            env,
+            jsx,
+            decorator: DecoratorOptions {
+                legacy: self.experimental_decorators,
+                emit_decorator_metadata: self.experimental_decorators
+                    && self.emit_decorator_metadata,
+            },
+            ..TransformOptions::default()
+        };
+        if let Some(factory) = &self.jsx_factory {
+            options.typescript.jsx_pragma = factory.clone().into();
+        }
+        if let Some(fragment) = &self.jsx_fragment_factory {
+            options.typescript.jsx_pragma_frag = fragment.clone().into();
+        }
+        options
+    }
+
+    fn apply_config_file(&mut self, path: &Path, depth: usize) {
+        let Some(json) = read_jsonc(path) else {
+            return;
+        };
+
+        // `extends` is applied first so this file's own options win
+        if depth < MAX_EXTENDS_DEPTH {
+            if let Some(base) = json.get("extends").and_then(Value::as_str) {
+                if base.starts_with('.') {
+                    let base_path = path.parent().unwrap_or(Path::new("")).join(base);
+                    let base_path = if base_path.extension().is_none() {
+                        base_path.with_extension("json")
+                    } else {
+                        base_path
+                    };
+                    self.apply_config_file(&base_path, depth + 1);
+                }
+            }
+        }
+
+        let Some(options) = json.get("compilerOptions") else {
+            return;
+        };
+        let str_opt = |key: &str| options.get(key).and_then(Value::as_str);
+        let bool_opt = |key: &str| options.get(key).and_then(Value::as_bool);
+
+        if let Some(jsx) = str_opt("jsx").and_then(JsxMode::parse) {
+            self.jsx = jsx;
+        }
+        if let Some(source) = str_opt("jsxImportSource") {
+            self.jsx_import_source = Some(source.to_string());
+        }
+        if let Some(factory) = str_opt("jsxFactory") {
+            self.jsx_factory = Some(factory.to_string());
+        }
+        if let Some(fragment) = str_opt("jsxFragmentFactory") {
+            self.jsx_fragment_factory = Some(fragment.to_string());
+        }
+        if let Some(enabled) = bool_opt("experimentalDecorators") {
+            self.experimental_decorators = enabled;
+        }
+        if let Some(enabled) = bool_opt("emitDecoratorMetadata") {
+            self.emit_decorator_metadata = enabled;
+        }
+        if let Some(target) = str_opt("target") {
+            self.target = target.to_ascii_lowercase();
+        }
+    }
+}
+
+/// True for files whose change must drop all resolved settings.
+pub fn is_config_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|name| CONFIG_FILES.contains(&name))
+}
+
+pub fn invalidate_all() {
+    DIR_SETTINGS.lock().unwrap().clear();
+}
+
+/// Config files from `dir` up to `root`, nearest first; none for a `dir`
+/// outside it. At most one file per directory (`tsconfig.json` wins over
+/// `jsconfig.json`).
+fn config_files_above(dir: &Path, root: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    if !dir.starts_with(root) {
+        return found;
+    }
+    for dir in dir.ancestors() {
+        if let Some(file) = CONFIG_FILES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|file| file.is_file())
+        {
+            found.push(file);
+        }
+        if dir == root {
+            break;
+        }
+    }
+    found
+}
+
+fn read_jsonc(path: &Path) -> Option<Value> {
+    let text = fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&strip_jsonc(&text)) {
+        Ok(json) => Some(json),
+        Err(e) => {
+            eprintln!("[Config] Failed to parse {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Drops `//` and `/* */` comments and trailing commas, which tsconfig allows.
+fn strip_jsonc(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            stripped.push(c);
+            match c {
+                '\\' => stripped.extend(chars.next()),
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        stripped.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => {
+                in_string = c == '"';
+                stripped.push(c);
+            }
+        }
+    }
+
+    // Second pass: a comma followed only by whitespace and `}`/`]` is dropped
+    let mut out = String::with_capacity(stripped.len());
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in stripped.char_indices() {
+        if in_string {
+            in_string = escaped || c != '"';
+            escaped = !escaped && c == '\\';
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ',' {
+            let next = stripped[i + 1..].trim_start();
+            if next.starts_with('}') || next.starts_with(']') {
+                continue;
+            }
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fresh directory under the system temp dir.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("wss-transform-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn strip_jsonc_keeps_strings() {
+        let text = r#"{
+            // line comment
+            "a": "http://x/*y*/", /* block
+            comment */ "b": [1, 2,],
+            "c": "quote \" // not a comment",
+        }"#;
+        let json: Value = serde_json::from_str(&strip_jsonc(text)).unwrap();
+        assert_eq!(json["a"], "http://x/*y*/");
+        assert_eq!(json["b"], serde_json::json!([1, 2]));
+        assert_eq!(json["c"], "quote \" // not a comment");
+    }
+
+    #[test]
+    fn extends_applies_base_first() {
+        let dir = scratch("extends");
+        fs::write(
+            dir.join("base.json"),
+            r#"{ "compilerOptions": { "jsx": "react", "jsxFactory": "h", "target": "ES2019" } }"#,
+        )
+        .unwrap();
+        fs::write(
+            dir.join("tsconfig.json"),
+            r#"{ "extends": "./base", "compilerOptions": { "target": "es2022" } }"#,
+        )
+        .unwrap();
+
+        let mut settings = TransformSettings::from_env();
+        settings.apply_config_file(&dir.join("tsconfig.json"), 0);
+        assert_eq!(settings.jsx, JsxMode::Classic);
+        assert_eq!(settings.jsx_factory.as_deref(), Some("h"));
+        assert_eq!(settings.target, "es2022");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn extends_stops_at_depth_limit() {
+        let dir = scratch("depth");
+        // A chain c0 -> c1 -> ...; only the last file sets the target, one
+        // `extends` beyond the limit from c0
+        let last = MAX_EXTENDS_DEPTH + 1;
+        for i in 0..=last {
+            let content = if i == last {
+                r#"{ "compilerOptions": { "target": "es5" } }"#.to_string()
+            } else {
+                format!(r#"{{ "extends": "./c{}.json" }}"#, i + 1)
+            };
+            fs::write(dir.join(format!("c{}.json", i)), content).unwrap();
+        }
+        let mut settings = TransformSettings::from_env();
+        settings.apply_config_file(&dir.join("c1.json"), 0);
+        assert_eq!(settings.target, "es5");
+        let mut settings = TransformSettings::from_env();
+        settings.apply_config_file(&dir.join("c0.json"), 0);
+        assert_ne!(settings.target, "es5");
+
+        // A cycle ends at the limit too
+        fs::write(dir.join("loop.json"), r#"{ "extends": "./loop.json" }"#).unwrap();
+        TransformSettings::from_env().apply_config_file(&dir.join("loop.json"), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn config_lookup_stops_at_root() {
+        let outer = scratch("lookup");
+        let root = outer.join("project");
+        let nested = root.join("src/app");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(outer.join("tsconfig.json"), "{}").unwrap();
+        fs::write(root.join("jsconfig.json"), "{}").unwrap();
+        fs::write(nested.join("tsconfig.json"), "{}").unwrap();
+        fs::write(nested.join("jsconfig.json"), "{}").unwrap();
+
+        assert_eq!(
+            config_files_above(&nested, &root),
+            [nested.join("tsconfig.json"), root.join("jsconfig.json")]
+        );
+        assert!(config_files_above(&outer, &root).is_empty());
+        fs::remove_dir_all(&outer).unwrap();
+    }
+}
@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+use oxc_allocator::Allocator;
+use oxc_codegen::{Codegen, CodegenOptions};
+use oxc_parser::Parser;
+use oxc_semantic::SemanticBuilder;
+use oxc_span::SourceType;
+use oxc_transformer::Transformer;
+
+use super::deps;
+use super::diagnostics::{self, Diagnostic};
+use super::resolve;
+use super::transform_options::TransformSettings;
+use crate::config::{config, SourceMapMode};
+
+/// Output of [`transpile_ts_to_js`]. When `diagnostics` is non-empty, `code`
+/// is an error module that throws instead of the (broken) generated JS.
+pub struct Transpiled {
+    pub code: String,
+    /// Source map (JSON), unless `SOURCE_MAPS=off`
+    pub map: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// `/project` module pipeline up to import rewriting: TS/JSX is transpiled,
+/// CommonJS dependencies converted to ESM, other JS passed through. The
+/// result depends only on the source and `settings`, so it can be persisted;
+/// `resolve::rewrite_imports` still has to run on it.
+pub fn transform_module(source: &str, path: &Path, settings: &TransformSettings) -> Transpiled {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    if matches!(ext, "ts" | "tsx" | "jsx" | "mts") {
+        transpile_ts_to_js(source, path, settings)
+    } else {
+        let code = if deps::is_dependency(path) && deps::is_commonjs(source, path) {
+            deps::to_esm(source, path)
+        } else {
+            source.to_string()
+        };
+        Transpiled {
+            code,
+            map: None,
+            diagnostics: Vec::new(),
+        }
+    }
+}
+
+pub fn transpile_ts_to_js(source: &str, path: &Path, settings: &TransformSettings) -> Transpiled {
+    let allocator = Allocator::default();
+    let source_type = SourceType::from_path(path).unwrap_or_else(|_| SourceType::ts());
+
+    // 1. Parse
+    let parse_ret = Parser::new(&allocator, source, source_type).parse();
+
+    if !parse_ret.errors.is_empty() {
+        eprintln!("[OXC] Parse errors in {}:", path.display());
+        for err in &parse_ret.errors {
+            eprintln!("{:?}", err);
+        }
+    }
+    let errors = diagnostics::collect(&parse_ret.errors, source, path);
+    if !errors.is_empty() {
+        return failed(errors);
+    }
+
+    let mut program = parse_ret.program;
+
+    // 2. Semantic info (needed by transformer)
+    let semantic_ret = SemanticBuilder::new()
+        .with_excess_capacity(2.0)
+        .build(&program);
+
+    if !semantic_ret.errors.is_empty() {
+        eprintln!("[OXC] Semantic errors in {}:", path.display());
+        for err in &semantic_ret.errors {
+            eprintln!("{:?}", err);
+        }
+    }
+    let mut errors = diagnostics::collect(&semantic_ret.errors, source, path);
+
+    let scoping = semantic_ret.semantic.into_scoping();
+
+    // 3. Transform (TS/JSX, target and decorators per tsconfig/server config)
+    let options = settings.to_oxc();
+
+    let transform_ret =
+        Transformer::new(&allocator, path, &options).build_with_scoping(scoping, &mut program);
+
+    if !transform_ret.errors.is_empty() {
+        eprintln!("[OXC] Transform errors in {}:", path.display());
+        for err in &transform_ret.errors {
+            eprintln!("{:?}", err);
+        }
+    }
+    errors.extend(diagnostics::collect(&transform_ret.errors, source, path));
+
+    if !errors.is_empty() {
+        return failed(errors);
+    }
+
+    // 4. Emit JS (+ source map pointing back at the `/project/...` URL)
+    let mode = config().source_maps;
+    let codegen_options = CodegenOptions {
+        source_map_path: (mode != SourceMapMode::Off)
+            .then(|| PathBuf::from(resolve::file_url(path))),
+        ..CodegenOptions::default()
+    };
+    let codegen_ret = Codegen::new().with_options(codegen_options).build(&program);
+    let mut code = codegen_ret.code;
+    let mut map = None;
+
+    if let Some(source_map) = codegen_ret.map {
+        match mode {
+            SourceMapMode::Inline => {
+                code.push_str(&format!(
+                    "\n//# sourceMappingURL={}\n",
+                    source_map.to_data_url()
+                ));
+            }
+            SourceMapMode::Sidecar => {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                code.push_str(&format!("\n//# sourceMappingURL={}.map\n", file_name));
+            }
+            SourceMapMode::Off => {}
+        }
+        // Kept in both modes: the server needs it to remap preview stack traces
+        map = Some(source_map.to_json_string());
+    }
+
+    Transpiled {
+        code,
+        map,
+        diagnostics: Vec::new(),
+    }
+}
+
+fn failed(diagnostics: Vec<Diagnostic>) -> Transpiled {
+    Transpiled {
+        code: diagnostics::error_module(&diagnostics),
+        map: None,
+        diagnostics,
+    }
+}
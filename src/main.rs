@@ -1,22 +1,25 @@
-use std::collections::HashMap;
 use std::env;
+use std::path::{Path, PathBuf};
 
-use actix_files::Files;
-use actix_web::{middleware::Logger, web, App, HttpServer};
+use actix_web::middleware::{from_fn, Compress, Logger};
+use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
 use once_cell::sync::OnceCell;
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 
 // local modules
+mod build;
 mod cmd;
+mod config;
 mod http;
 mod watcher;
 mod ws;
 
-use http::routes::{index, project};
+use http::routes::{index, project, runtime_script, web_file};
 use watcher::start_watcher;
 use ws::connection::{handler, start_watcher_event_broadcast, Clients, WatcherEvent};
+use ws::registry::Registry;
 
 // Use OnceCell to ensure the broadcast task is spawned only once
 static BROADCAST_TASK_SPAWNED: OnceCell<()> = OnceCell::new();
@@ -25,14 +28,57 @@ static BROADCAST_TASK_SPAWNED: OnceCell<()> = OnceCell::new();
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
     env::set_var("RUST_LOG", "info");
-    let clients: Clients = Arc::new(Mutex::new(HashMap::new()));
+    let clients: Clients = Arc::new(Mutex::new(Registry::default()));
     env_logger::init();
 
+    let mut args: Vec<String> = env::args().collect();
+    // Flags shared by the server and `build`; config reads them from the env
+    if let Some(i) = args.iter().position(|arg| arg == "--project") {
+        if let Some(dir) = args.get(i + 1) {
+            env::set_var("PROJECT_DIR", dir);
+        }
+        args.drain(i..(i + 2).min(args.len()));
+    }
+    if let Some(i) = args.iter().position(|arg| arg == "--dev") {
+        args.remove(i);
+        // Serve the IDE and runtime from this checkout, edits apply on reload;
+        // a binary moved off the build machine keeps its embedded copies
+        let source = Path::new(env!("CARGO_MANIFEST_DIR"));
+        for (var, dir) in [("WEB_DIR", "web"), ("INJECT_SCRIPTS_DIR", "inject_scripts")] {
+            let dir = source.join(dir);
+            if env::var_os(var).is_some() {
+                continue;
+            }
+            if dir.is_dir() {
+                env::set_var(var, dir);
+            } else {
+                println!("[Dev] {} not found, using the embedded copy", dir.display());
+            }
+        }
+    }
+
+    if args.get(1).map(String::as_str) == Some("build") {
+        return build::run(&args[2..]);
+    }
+
     let host = env::var("HOST").unwrap_or_else(|_| "127.0.0.1".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "8080".to_string());
     let addr = format!("{}:{}", host, port);
 
-    let mut project_path = "./".to_string(); // The directory to watch
+    // A project outside the working directory is watched on its own; events
+    // are then keyed by its (absolute) root like every other project path.
+    // The working directory itself is `.`, so its keys start with `./` too.
+    let project_root = http::routes::project_root();
+    let key_prefix = if project_root.is_absolute() || project_root == Path::new(".") {
+        project_root.to_path_buf()
+    } else {
+        PathBuf::new()
+    };
+    let mut project_path = if project_root.is_absolute() {
+        project_root.display().to_string()
+    } else {
+        "./".to_string() // The directory to watch
+    };
     let canonical_project_path = match std::fs::canonicalize(&project_path) {
         Ok(path) => path.to_string_lossy().to_string(),
         Err(e) => {
@@ -40,10 +86,7 @@ async fn main() -> std::io::Result<()> {
                 "Failed to canonicalize project path {}: {}",
                 project_path, e
             );
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to canonicalize project path",
-            ));
+            return Err(std::io::Error::other("Failed to canonicalize project path"));
         }
     };
     project_path = canonical_project_path.clone(); // Use the canonical path from now on
@@ -54,23 +97,30 @@ async fn main() -> std::io::Result<()> {
     let shared_watcher_rx = Arc::new(Mutex::new(Some(watcher_rx)));
 
     // Start the file watcher. It will send events to watcher_tx.
-    let _watcher = match start_watcher(project_path.clone(), watcher_tx.clone()) {
+    let _watcher = match start_watcher(project_path.clone(), key_prefix, watcher_tx.clone()) {
         Ok(watcher) => {
             println!("Started file watcher in: {}", project_path);
             watcher
         }
         Err(e) => {
             eprintln!("Failed to start file watcher: {}", e);
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Failed to start file watcher",
-            ));
+            return Err(std::io::Error::other("Failed to start file watcher"));
         }
     };
 
+    // Convert CommonJS dependencies in the background; requests convert on demand
+    std::thread::spawn(http::deps::prebundle_dependencies);
+
+    http::proxy::init();
+
     println!("Starting WebSocket server at ws://{}/ws/", addr);
+    // Same app on a second port; `security::headers` tells the two apart
+    let preview_port = port.parse().ok().and_then(http::security::preview_port);
+    if let Some(origin) = http::security::preview_origin() {
+        println!("Serving the project preview at {}", origin);
+    }
 
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         // Clone for this closure instance
         let clients_clone_for_factory = clients.clone();
         let shared_watcher_rx_clone_for_factory = shared_watcher_rx.clone();
@@ -85,19 +135,26 @@ async fn main() -> std::io::Result<()> {
                 // This should theoretically not happen if get_or_init is called only once
                 eprintln!("Watcher receiver was already taken, broadcast task not started.");
             }
-            () // Return unit type
         });
 
         App::new()
-            .service(Files::new("/web", "./web"))
+            .service(web_file)
             .service(index)
             // .service(Files::new("/project", project_path.clone()))
             .service(project)
+            .service(runtime_script)
+            // Skips responses that already carry a Content-Encoding
+            .wrap(Compress::default())
+            .wrap(from_fn(http::security::headers))
             .wrap(Logger::default())
             .app_data(web::Data::new(clients.clone()))
             .route("/ws/", web::get().to(handler))
+            // Anything unmatched may belong to a `PROXY_CONFIG` backend
+            .default_service(web::to(http::proxy::handle))
     })
-    .bind(addr)?
-    .run()
-    .await
+    .bind(addr)?;
+    if let Some(preview_port) = preview_port {
+        server = server.bind((host.as_str(), preview_port))?;
+    }
+    server.run().await
 }
@@ -1,3 +1,4 @@
+use crate::http::{cache, css, deps, graph, resolve, transform_options};
 use crate::ws::connection::WatcherEvent;
 use notify::{Config, RecommendedWatcher, RecursiveMode, Watcher};
 use std::collections::HashMap;
@@ -8,9 +9,18 @@ use tokio::sync::mpsc;
 
 const IGNORED_DIRS: &[&str] = &["target/", ".git/"];
 
+/// Ignored wherever they appear in the path, not just at the root.
+const IGNORED_DIR_NAMES: &[&str] = &["node_modules"];
+
 const IGNORED_FILENAMES: &[&str] = &["~", ".swp", ".swo", ".tmp"];
 
-fn should_ignore_relative(relative_path: &str) -> bool {
+fn is_script(relative_path: &str) -> bool {
+    [".js", ".mjs", ".jsx", ".ts", ".mts", ".tsx"]
+        .iter()
+        .any(|ext| relative_path.ends_with(ext))
+}
+
+pub fn should_ignore_relative(relative_path: &str) -> bool {
     // Ignore entire directories by relative prefix
     for dir in IGNORED_DIRS {
         if relative_path.starts_with(dir.trim_end_matches('/')) {
@@ -18,6 +28,13 @@ fn should_ignore_relative(relative_path: &str) -> bool {
         }
     }
 
+    if Path::new(relative_path)
+        .components()
+        .any(|c| IGNORED_DIR_NAMES.iter().any(|dir| c.as_os_str() == *dir))
+    {
+        return true;
+    }
+
     // Ignore filename patterns
     if let Some(filename) = Path::new(relative_path)
         .file_name()
@@ -33,14 +50,21 @@ fn should_ignore_relative(relative_path: &str) -> bool {
 
     false
 }
+fn url_path(file: &Path) -> String {
+    resolve::file_url(file).trim_start_matches('/').to_string()
+}
+
 // Thread-safe debounce tracker
 lazy_static::lazy_static! {
     static ref DEBOUNCE_MAP: Arc<Mutex<HashMap<String, Instant>>> =
         Arc::new(Mutex::new(HashMap::new()));
 }
 
+/// Watches `project_path` recursively. Changed files are keyed (for caches
+/// and the module graph) as `key_prefix` joined with their path below it.
 pub fn start_watcher(
     project_path: String,
+    key_prefix: PathBuf,
     tx: mpsc::UnboundedSender<WatcherEvent>,
 ) -> Result<RecommendedWatcher, Box<dyn std::error::Error>> {
     println!("[Watcher] Starting watcher for path: {}", project_path);
@@ -63,17 +87,47 @@ pub fn start_watcher(
                         for path in event.paths {
                             println!("[Watcher] Path: {:?}", path);
 
-                            if let Ok(relative_path_buf) = path.strip_prefix(&project_path_obj) {
-                                let relative_path = relative_path_buf.to_string_lossy().to_string();
-
+                            if let Ok(watched_relative) = path.strip_prefix(&project_path_obj) {
                                 // Ignore FIRST, using relative path
-                                if should_ignore_relative(&relative_path) {
-                                    println!("[Watcher] Ignoring: {}", relative_path);
+                                if should_ignore_relative(&watched_relative.to_string_lossy()) {
+                                    println!("[Watcher] Ignoring: {}", watched_relative.display());
                                     continue;
                                 }
+                                let file = key_prefix.join(watched_relative);
+                                let relative_path_buf = file.as_path();
+                                // Clients address files by URL, minus the leading slash
+                                let relative_path = url_path(relative_path_buf);
                                 println!("[Watcher] Event: {:?}", event.kind);
                                 println!("[Watcher] Path: {:?}", path);
 
+                                // Stale transpile output must go even if the event is debounced
+                                if transform_options::is_config_file(relative_path_buf) {
+                                    transform_options::invalidate_all();
+                                    cache::clear();
+                                } else if resolve::is_resolution_file(relative_path_buf) {
+                                    resolve::invalidate();
+                                    cache::clear();
+                                } else if deps::is_lockfile(relative_path_buf) {
+                                    deps::invalidate();
+                                    cache::clear();
+                                } else {
+                                    cache::invalidate(relative_path_buf);
+                                }
+
+                                // JSON/text/asset imports carry a version in the importer's
+                                // output, so the importer itself is stale now
+                                let importers = if is_script(&relative_path)
+                                    || relative_path.ends_with(".css")
+                                    || relative_path.ends_with(".html")
+                                {
+                                    Vec::new()
+                                } else {
+                                    graph::importers(relative_path_buf)
+                                };
+                                for importer in &importers {
+                                    cache::invalidate(importer);
+                                }
+
                                 // DEBOUNCE CHECK
                                 let debounce_key = format!("{:?}", path);
                                 let now = Instant::now();
@@ -100,37 +154,44 @@ pub fn start_watcher(
                                 } // Lock released here
 
                                 let watcher_event = if relative_path.ends_with(".css") {
+                                    // Stylesheets that @import this one need a refresh too
+                                    let dependents = css::dependents(relative_path_buf)
+                                        .iter()
+                                        .map(|p| resolve::file_url(p))
+                                        .collect();
                                     WatcherEvent::HmrCssUpdate {
                                         path: relative_path.clone(),
-                                        action: action,
+                                        action,
+                                        dependents,
                                     }
-                                } else if relative_path.ends_with(".js")
-                                    || relative_path.ends_with(".mjs")
-                                    || relative_path.ends_with(".jsx")
-                                    || relative_path.ends_with(".ts")
-                                    || relative_path.ends_with(".mts")
-                                    || relative_path.ends_with(".tsx")
-                                {
+                                } else if is_script(&relative_path) {
                                     WatcherEvent::HmrJsUpdate {
                                         path: relative_path.clone(),
-                                        action: action,
+                                        action,
                                     }
                                 } else if relative_path.ends_with(".html") {
                                     WatcherEvent::HmrReload {
                                         path: relative_path.clone(),
-                                        action: action,
+                                        action,
                                     }
                                 } else {
                                     let path_for_notify =
-                                        if path.metadata().map_or(false, |m| m.is_dir()) {
+                                        if path.metadata().is_ok_and(|m| m.is_dir()) {
                                             format!("{}/", relative_path)
                                         } else {
                                             relative_path
                                         };
-                                    println!("AAAAAAAAAAA {}", action);
+                                    for importer in importers {
+                                        let importer_event = WatcherEvent::HmrJsUpdate {
+                                            path: url_path(&importer),
+                                            action: action.clone(),
+                                        };
+                                        println!("[Watcher] 🚀 Sending: {:?}", importer_event);
+                                        let _ = tx.send(importer_event);
+                                    }
                                     WatcherEvent::NotifyUpdate {
                                         path: path_for_notify,
-                                        action: action,
+                                        action,
                                     }
                                 };
 
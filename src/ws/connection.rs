@@ -1,5 +1,6 @@
+use actix_web::http::header;
 use actix_web::{web, Error, HttpRequest, HttpResponse};
-use actix_ws::{handle, Message, Session};
+use actix_ws::{handle, CloseCode, CloseReason, Message, Session};
 use bytes::Bytes;
 use futures_util::StreamExt;
 use log::error;
@@ -11,16 +12,28 @@ use std::sync::{
     atomic::{AtomicUsize, Ordering},
     Arc, Mutex,
 };
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
+use tokio::time::{self, MissedTickBehavior};
 
+use super::payload::Payload;
+use super::queue;
+use super::registry::{self, Client, Filter, Hello, Registry, Role};
+use super::{console, eval, pubsub};
 use crate::cmd::nu::execute_command;
+use crate::config::config;
+use crate::http::diagnostics::Diagnostic;
+use crate::http::security::{self, Peer};
+use crate::http::sourcemap::remap_stack;
 // The WatcherMessage enum is internal to the watcher module, we now deal with WatcherEvent
 // use crate::watcher::WatcherMessage; // This import is no longer needed directly
 
 #[derive(Deserialize)]
 struct ClientMessage {
     r#type: String,
-    body: String,
+    /// A string for most types; `publish` and `broadcast` take any value
+    #[serde(default)]
+    body: Payload,
     msg_id: String,
 }
 
@@ -34,18 +47,61 @@ enum WsMessage {
 // Update enum to HMR types
 #[derive(Serialize, Clone, Debug)]
 pub enum WatcherEvent {
-    HmrReload { path: String, action: String },
-    HmrCssUpdate { path: String, action: String },
-    HmrJsUpdate { path: String, action: String },
-    NotifyUpdate { path: String, action: String },
+    HmrReload {
+        path: String,
+        action: String,
+    },
+    HmrCssUpdate {
+        path: String,
+        action: String,
+        /// `/project/...` URLs of every stylesheet affected by the change
+        dependents: Vec<String>,
+    },
+    HmrJsUpdate {
+        path: String,
+        action: String,
+    },
+    NotifyUpdate {
+        path: String,
+        action: String,
+    },
+}
+
+impl WatcherEvent {
+    /// Clients that act on the event: previews only care about HMR.
+    fn audience(&self) -> Filter {
+        match self {
+            WatcherEvent::NotifyUpdate { .. } => Filter::roles(&[Role::Ide, Role::Tool]),
+            _ => Filter::default(),
+        }
+    }
+
+    /// Identical events still queued for a slow client are only sent once.
+    fn coalescing_key(&self) -> String {
+        match self {
+            WatcherEvent::HmrReload { path, action } => format!("hmr::reload:{}:{}", action, path),
+            WatcherEvent::HmrCssUpdate { path, action, .. } => {
+                format!("hmr::css_update:{}:{}", action, path)
+            }
+            WatcherEvent::HmrJsUpdate { path, action } => {
+                format!("hmr::js_update:{}:{}", action, path)
+            }
+            WatcherEvent::NotifyUpdate { path, action } => {
+                format!("notify::update:{}:{}", action, path)
+            }
+        }
+    }
 }
 
 #[derive(Serialize, Clone, Debug)]
 pub struct HmrMessage {
     #[serde(rename = "type")]
     pub msg_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub action: Option<String>,
     pub body: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependents: Vec<String>,
 }
 
 impl From<WatcherEvent> for HmrMessage {
@@ -55,16 +111,23 @@ impl From<WatcherEvent> for HmrMessage {
                 msg_type: "hmr::reload".to_string(),
                 action: Some(action),
                 body: format!("/{}", path),
+                dependents: Vec::new(),
             },
-            WatcherEvent::HmrCssUpdate { path, action } => HmrMessage {
+            WatcherEvent::HmrCssUpdate {
+                path,
+                action,
+                dependents,
+            } => HmrMessage {
                 msg_type: "hmr::css_update".to_string(),
                 action: Some(action),
                 body: format!("/{}", path),
+                dependents,
             },
             WatcherEvent::HmrJsUpdate { path, action } => HmrMessage {
                 msg_type: "hmr::js_update".to_string(),
                 action: Some(action),
                 body: format!("/{}", path),
+                dependents: Vec::new(),
             },
             WatcherEvent::NotifyUpdate { path, action } => {
                 // Detect action from Notify event.kind (pass it through WatcherEvent)
@@ -72,6 +135,7 @@ impl From<WatcherEvent> for HmrMessage {
                     msg_type: "notify::update".to_string(),
                     action: Some(action),
                     body: format!("/{}", path),
+                    dependents: Vec::new(),
                 }
             } // WatcherEvent::NotifyUpdate { path } => HmrMessage {
               //     msg_type: "notify::update".to_string(),
@@ -84,13 +148,20 @@ impl From<WatcherEvent> for HmrMessage {
 #[derive(Serialize, Clone)]
 struct ServerMessage {
     r#type: String,
-    body: String,
+    body: Payload,
     id: usize,
     msg_id: String,
 }
 
-pub type Tx = mpsc::UnboundedSender<Message>;
-pub type Clients = Arc<Mutex<HashMap<usize, Tx>>>;
+#[derive(Serialize)]
+struct DiagnosticsMessage<'a> {
+    r#type: &'a str,
+    body: &'a str,
+    diagnostics: &'a [Diagnostic],
+}
+
+pub type Tx = queue::Sender;
+pub type Clients = Arc<Mutex<Registry>>;
 
 /// Used to assign unique IDs to clients.
 static NEXT_CLIENT_ID: AtomicUsize = AtomicUsize::new(1);
@@ -105,17 +176,14 @@ pub fn start_watcher_event_broadcast(
         while let Some(event) = rx.recv().await {
             println!("[Broadcast] Received: {:?}", event);
 
+            let audience = event.audience();
+            let key = event.coalescing_key();
             // SINGLE conversion - clone if needed
             let frontend_msg: HmrMessage = event.clone().into(); // Clone + into
 
             let mut buf = Vec::new();
-            let mut msg_map = HashMap::new();
-            msg_map.insert("type".to_string(), frontend_msg.msg_type.clone());
-            if let Some(action) = &frontend_msg.action {
-                msg_map.insert("action".to_string(), action.clone());
-            }
-            msg_map.insert("body".to_string(), frontend_msg.body.clone());
-            if let Err(e) = msg_map.serialize(&mut Serializer::new(&mut buf)) {
+            if let Err(e) = frontend_msg.serialize(&mut Serializer::new(&mut buf).with_struct_map())
+            {
                 error!("Serialize failed: {}", e);
                 continue;
             }
@@ -123,36 +191,58 @@ pub fn start_watcher_event_broadcast(
             let bytes = Bytes::from(buf);
 
             let guard = clients.lock().unwrap();
+            let sent = guard.broadcast_coalescing(&audience, &bytes, &key);
             println!(
-                "[Broadcast] Attempting to send event to {} clients.",
+                "[Broadcast] Sent event to {} of {} clients.",
+                sent,
                 guard.len()
             );
-            for (client_id, client) in guard.iter() {
-                if let Err(e) = client.send(Message::Binary(bytes.clone())) {
-                    error!(
-                        "Failed to send watcher event to client {}: {}",
-                        client_id, e
-                    );
-                } else {
-                    println!("[Broadcast] Sent event to client {}", client_id);
-                }
-            }
         }
         println!("[Broadcast] Watcher event broadcast task finished.");
     });
 }
 
+/// Sends transpile diagnostics for `path` (a `/project/...` URL) to every client.
+pub fn broadcast_diagnostics(
+    clients: &Clients,
+    msg_type: &str,
+    path: &str,
+    diagnostics: &[Diagnostic],
+) {
+    let mut buf = Vec::new();
+    let msg = DiagnosticsMessage {
+        r#type: msg_type,
+        body: path,
+        diagnostics,
+    };
+    if let Err(e) = msg.serialize(&mut Serializer::new(&mut buf).with_struct_map()) {
+        error!("Serialize failed: {}", e);
+        return;
+    }
+
+    let bytes = Bytes::from(buf);
+    clients
+        .lock()
+        .unwrap()
+        .broadcast(&Filter::default(), &bytes);
+}
+
 async fn handle_binary_message(
     id: usize,
+    peer: Peer,
     bin: Bytes,
     session: &mut Session,
     clients: &web::Data<Clients>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut de = Deserializer::new(Cursor::new(&bin));
     match ClientMessage::deserialize(&mut de) {
+        Ok(client_msg) if !peer.may_send(&client_msg.r#type) => {
+            error!("preview client {} may not send {}", id, client_msg.r#type);
+        }
         Ok(client_msg) if client_msg.r#type == "cmd" => {
-            println!("{:?} =>", client_msg.body);
-            match execute_command(&client_msg.body).await {
+            let command = client_msg.body.as_str().unwrap_or_default();
+            println!("{:?} =>", command);
+            match execute_command(command).await {
                 Ok(out) => {
                     println!("{}", out);
 
@@ -170,25 +260,53 @@ async fn handle_binary_message(
                 }
             }
         }
+        Ok(client_msg) if client_msg.r#type.starts_with(console::PREFIX) => {
+            // A malformed entry isn't worth the connection
+            if let Err(e) = console::relay(id, &bin, clients) {
+                error!("console entry from client {} dropped: {}", id, e);
+            }
+        }
+        Ok(client_msg) if client_msg.r#type.starts_with(registry::PREFIX) => {
+            if let Err(e) = registry::handle(id, peer, &client_msg.r#type, &bin, clients) {
+                error!("client message from client {} dropped: {}", id, e);
+            }
+        }
+        Ok(client_msg) if pubsub::handles(&client_msg.r#type) => {
+            if let Err(e) = pubsub::handle(id, &client_msg.r#type, &bin, clients) {
+                error!("pub/sub message from client {} dropped: {}", id, e);
+            }
+        }
+        Ok(client_msg) if client_msg.r#type.starts_with(eval::PREFIX) => {
+            if let Err(e) = eval::handle(id, &client_msg.r#type, &bin, clients) {
+                error!("eval message from client {} dropped: {}", id, e);
+            }
+        }
+        Ok(client_msg) if client_msg.r#type == "stack::remap" => {
+            let mut buf = Vec::new();
+            let mut reply_map = HashMap::new();
+            reply_map.insert("type".to_string(), "stack::remap_result".to_string());
+            reply_map.insert(
+                "body".to_string(),
+                remap_stack(client_msg.body.as_str().unwrap_or_default()),
+            );
+            reply_map.insert("msg_id".to_string(), client_msg.msg_id);
+            reply_map.serialize(&mut Serializer::new(&mut buf))?;
+            session.binary(buf).await?;
+        }
+        // Optional `roles`, `workspace` and `topic` narrow who gets it
         Ok(client_msg) if client_msg.r#type == "broadcast" => {
+            let mut filter = Filter::deserialize(&mut Deserializer::new(Cursor::new(&bin)))?;
+            filter.except = Some(id);
             let mut buf = Vec::new();
             let broadcast = WsMessage::Server(ServerMessage {
                 r#type: "broadcast".into(),
-                body: client_msg.body.clone(),
+                body: client_msg.body,
                 id,
-                msg_id: client_msg.msg_id.clone(),
+                msg_id: client_msg.msg_id,
             });
             broadcast.serialize(&mut Serializer::new(&mut buf))?;
             let bytes = Bytes::from(buf);
-
-            let guard = clients.lock().unwrap();
-            for (client_id, client) in guard.iter() {
-                if *client_id != id {
-                    if let Err(e) = client.send(Message::Binary(bytes.clone())) {
-                        error!("broadcast failed: {}", e);
-                    }
-                }
-            }
+            clients.lock().unwrap().broadcast(&filter, &bytes);
         }
         Ok(other) => {
             error!("unknown msg type: {}", other.r#type);
@@ -206,47 +324,116 @@ pub async fn handler(
     payload: web::Payload,
     clients: web::Data<Clients>,
 ) -> Result<HttpResponse, Error> {
+    let Some(peer) = security::ws_peer(&req) else {
+        return Ok(HttpResponse::Forbidden().body("WebSocket origin not allowed"));
+    };
+    let hello = web::Query::<Hello>::from_query(req.query_string())
+        .map(web::Query::into_inner)
+        .unwrap_or_default();
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let role = Role::default_for(peer, req.headers().contains_key(header::ORIGIN));
+
     let (response, mut session, mut msg_stream) = handle(&req, payload)?;
-    let (tx, mut rx) = mpsc::unbounded_channel();
     let id = NEXT_CLIENT_ID.fetch_add(1, Ordering::Relaxed);
-    clients.lock().unwrap().insert(id, tx);
+    let (tx, mut rx) = queue::channel(id);
+    let mut client = Client::new(tx, role, user_agent);
+    client.update(hello, peer);
+    // Console output from before this IDE window connected
+    let backlog = if client.role != Role::Preview {
+        console::history()
+    } else {
+        Vec::new()
+    };
+    clients.lock().unwrap().insert(id, client);
+    registry::joined(&clients, id);
     let clients_clone = clients.clone();
 
+    let settings = config();
+    let heartbeat_timeout = settings.heartbeat_timeout;
+    // Only polled when heartbeats are on
+    let period = settings
+        .heartbeat_interval
+        .unwrap_or(Duration::from_secs(3600));
+    let mut heartbeat = time::interval_at(time::Instant::now() + period, period);
+    heartbeat.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut last_seen = Instant::now();
+
     actix_web::rt::spawn(async move {
-        loop {
+        // Ahead of anything queued meanwhile; a dead socket ends the loop below
+        for bin in backlog {
+            if let Err(e) = session.binary(bin).await {
+                error!("console history replay failed: {}", e);
+                break;
+            }
+        }
+        let reason = loop {
             tokio::select! {
-                Some(Ok(msg)) = msg_stream.next() => match msg {
-                    Message::Binary(bin) => {
-                        if handle_binary_message(id, bin, &mut session, &clients_clone).await.is_err() {
-                            break;
+                msg = msg_stream.next() => {
+                    // Any frame proves the connection is alive, not just pongs
+                    last_seen = Instant::now();
+                    match msg {
+                        Some(Ok(Message::Binary(bin))) => {
+                            if handle_binary_message(id, peer, bin, &mut session, &clients_clone).await.is_err() {
+                                break "error";
+                            }
                         }
+                        Some(Ok(Message::Close(reason))) => { let _ = session.close(reason).await; break "closed"; }
+                        Some(Ok(Message::Ping(p))) => { let _ = session.pong(&p).await; }
+                        Some(Ok(Message::Pong(_) | Message::Text(_) | Message::Continuation(_) | Message::Nop)) => {}
+                        Some(Err(e)) => {
+                            error!("client {} protocol error: {}", id, e);
+                            break "error";
+                        }
+                        // TCP closed without a close frame
+                        None => break "closed",
+                    }
+                },
+                _ = heartbeat.tick(), if settings.heartbeat_interval.is_some() => {
+                    if last_seen.elapsed() > heartbeat_timeout {
+                        println!("[Heartbeat] Client {} missed its pongs, disconnecting", id);
+                        let _ = session.close(None).await;
+                        break "timeout";
+                    }
+                    if session.ping(b"").await.is_err() {
+                        break "closed";
                     }
-                    Message::Close(reason) => { let _ = session.close(reason).await; break; }
-                    Message::Ping(p)    => { let _ = session.pong(&p).await; }
-                    Message::Pong(_)    | Message::Text(_) | Message::Continuation(_) | Message::Nop => {}
                 },
-                Some(out_msg) = rx.recv() => match out_msg {
-                    Message::Binary(bin) => {
+                out_msg = rx.recv() => match out_msg {
+                    Some(Message::Binary(bin)) => {
                         if let Err(e) = session.binary(bin).await {
                             error!("outgoing binary failed: {}", e);
-                            break;
+                            break "error";
                         }
                     }
-                    Message::Text(txt) => {
+                    Some(Message::Text(txt)) => {
                         if let Err(e) = session.text(txt).await {
                             error!("outgoing text failed: {}", e);
-                            break;
+                            break "error";
                         }
                     }
-                    Message::Close(c) => { let _ = session.close(c).await; break; }
-                    Message::Ping(p)  => { let _ = session.ping(&p).await; }
-                    Message::Pong(p)  => { let _ = session.pong(&p).await; }
-                    _ => {}
+                    Some(Message::Close(c)) => { let _ = session.close(c).await; break "closed"; }
+                    Some(Message::Ping(p))  => { let _ = session.ping(&p).await; }
+                    Some(Message::Pong(p))  => { let _ = session.pong(&p).await; }
+                    Some(_) => {}
+                    // Disconnected for not keeping up
+                    None => {
+                        let reason = CloseReason {
+                            code: CloseCode::Again,
+                            description: Some("send queue full".to_string()),
+                        };
+                        let _ = session.close(Some(reason)).await;
+                        break "queue full";
+                    }
                 },
-                else => break,
             }
-        }
-        clients_clone.lock().unwrap().remove(&id);
+        };
+        registry::leave(&clients_clone, id, reason);
+        eval::forget(id, &clients_clone);
     });
     Ok(response)
 }
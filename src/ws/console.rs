@@ -0,0 +1,122 @@
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::sync::Mutex;
+
+use bytes::Bytes;
+use log::error;
+use rmp_serde::Deserializer;
+use serde::{Deserialize, Serialize};
+
+use super::connection::Clients;
+use super::registry::{self, Filter, Role};
+use crate::config::config;
+use crate::http::sourcemap::remap_stack;
+
+/// Message types the preview runtime sends: `console::log`, `::warn`, ...,
+/// `::exception` (uncaught errors), `::rejection` and `::clear`.
+pub const PREFIX: &str = "console::";
+
+/// One console call or uncaught error, as serialized by
+/// `console-override.js` (values it can't represent come as `{ $type }` maps).
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ConsoleEntry {
+    /// `<page id>:<seq>`, so a framing IDE can drop its `postMessage` copy
+    pub id: String,
+    pub level: String,
+    #[serde(default)]
+    pub args: Vec<serde_json::Value>,
+    /// Call site or error stack, remapped to the original sources
+    #[serde(default)]
+    pub stack: String,
+    /// Page the entry came from
+    #[serde(default)]
+    pub url: String,
+    /// Milliseconds since the Unix epoch, by the page's clock
+    #[serde(default)]
+    pub time: f64,
+}
+
+#[derive(Deserialize)]
+struct Incoming {
+    entry: ConsoleEntry,
+}
+
+#[derive(Serialize)]
+struct Relayed<'a> {
+    r#type: String,
+    /// Page URL, like other messages carry their subject in `body`
+    body: &'a str,
+    /// Sending client
+    client: usize,
+    entry: &'a ConsoleEntry,
+    /// Replayed to a client that connected after it was logged
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    history: bool,
+}
+
+lazy_static::lazy_static! {
+    /// Recent entries with their sender, oldest first (`CONSOLE_HISTORY` long)
+    static ref HISTORY: Mutex<VecDeque<(usize, ConsoleEntry)>> = Mutex::new(VecDeque::new());
+}
+
+/// Passes a `console::*` message from client `id` on to the IDE and tool
+/// clients and keeps it for late joiners. `console::clear` empties the history.
+pub fn relay(id: usize, bin: &[u8], clients: &Clients) -> Result<(), Box<dyn std::error::Error>> {
+    let mut de = Deserializer::new(Cursor::new(bin));
+    let Incoming { mut entry } = Incoming::deserialize(&mut de)?;
+    if !entry.stack.is_empty() {
+        entry.stack = remap_stack(&entry.stack);
+    }
+
+    let bytes = encode(id, &entry, false)?;
+    {
+        let mut history = HISTORY.lock().unwrap();
+        if entry.level == "clear" {
+            history.clear();
+        } else {
+            let limit = config().console_history;
+            while limit > 0 && history.len() >= limit {
+                history.pop_front();
+            }
+            if limit > 0 {
+                history.push_back((id, entry));
+            }
+        }
+    }
+
+    let mut audience = Filter::roles(&[Role::Ide, Role::Tool]);
+    audience.except = Some(id);
+    clients.lock().unwrap().broadcast(&audience, &bytes);
+    Ok(())
+}
+
+/// The buffered entries for a newly connected client, oldest first. They
+/// can outnumber `CLIENT_QUEUE_SIZE`, so the caller writes them to the socket
+/// itself instead of queueing them.
+pub fn history() -> Vec<Bytes> {
+    let history = HISTORY.lock().unwrap();
+    history
+        .iter()
+        .filter_map(|(client, entry)| match encode(*client, entry, true) {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                error!("console history serialize failed: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+fn encode(
+    client: usize,
+    entry: &ConsoleEntry,
+    history: bool,
+) -> Result<Bytes, rmp_serde::encode::Error> {
+    registry::encode(&Relayed {
+        r#type: format!("{}{}", PREFIX, entry.level),
+        body: &entry.url,
+        client,
+        entry,
+        history,
+    })
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::error;
+use rmp_serde::Deserializer;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::time;
+
+use super::connection::Clients;
+use super::registry::{self, Filter, Role};
+
+/// Evaluating JavaScript in a preview page on behalf of an IDE client:
+///
+/// - IDE → `eval::request` (body: expression, `target`: client id, or the
+///   latest preview if absent) → forwarded to a client with the `preview`
+///   role (see `registry`)
+/// - preview → `eval::result` (`ok`, `value` or `error`) → back to the IDE,
+///   under the IDE's own `msg_id`; a failed result if none comes within
+///   `TIMEOUT`
+/// - IDE → `eval::targets` → `eval::targets` with `targets: [{client, url}]`
+pub const PREFIX: &str = "eval::";
+
+/// How long a preview gets to answer, e.g. one paused in the debugger
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Deserialize)]
+struct Request {
+    body: String,
+    msg_id: String,
+    #[serde(default)]
+    target: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct Reply {
+    msg_id: String,
+    #[serde(default)]
+    ok: bool,
+    #[serde(default)]
+    value: Value,
+    #[serde(default)]
+    error: Value,
+}
+
+#[derive(Serialize)]
+struct Forwarded<'a> {
+    r#type: &'a str,
+    body: &'a str,
+    msg_id: &'a str,
+    /// Requesting IDE client
+    from: usize,
+}
+
+#[derive(Serialize)]
+struct EvalResult<'a> {
+    r#type: &'a str,
+    body: &'a str,
+    msg_id: &'a str,
+    /// Preview client that evaluated it
+    client: usize,
+    ok: bool,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    value: &'a Value,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    error: &'a Value,
+}
+
+#[derive(Serialize)]
+struct Targets<'a> {
+    r#type: &'a str,
+    body: &'a str,
+    msg_id: &'a str,
+    targets: Vec<Target<'a>>,
+}
+
+#[derive(Serialize)]
+struct Target<'a> {
+    client: usize,
+    url: &'a str,
+}
+
+struct Pending {
+    requester: usize,
+    target: usize,
+    /// The requester's `msg_id`
+    msg_id: String,
+    sent: Instant,
+}
+
+lazy_static::lazy_static! {
+    /// In-flight requests by the id sent to the preview
+    static ref PENDING: Mutex<HashMap<String, Pending>> = Mutex::new(HashMap::new());
+}
+
+/// Handles an `eval::*` message from client `id`.
+pub fn handle(
+    id: usize,
+    msg_type: &str,
+    bin: &[u8],
+    clients: &Clients,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut de = Deserializer::new(Cursor::new(bin));
+    match msg_type {
+        "eval::targets" => {
+            let request = Request::deserialize(&mut de)?;
+            let previews = Filter::roles(&[Role::Preview]);
+            let bytes = {
+                let registry = clients.lock().unwrap();
+                registry::encode(&Targets {
+                    r#type: "eval::targets",
+                    body: "",
+                    msg_id: &request.msg_id,
+                    targets: registry
+                        .query(&previews)
+                        .map(|(client, preview)| Target {
+                            client,
+                            url: &preview.url,
+                        })
+                        .collect(),
+                })?
+            };
+            clients.lock().unwrap().send(id, bytes);
+        }
+        "eval::request" => {
+            let request = Request::deserialize(&mut de)?;
+            let target = {
+                let previews = Filter::roles(&[Role::Preview]);
+                let registry = clients.lock().unwrap();
+                let mut ready = registry.query(&previews).map(|(client, _)| client);
+                match request.target {
+                    Some(target) => ready.find(|client| *client == target),
+                    None => ready.last(),
+                }
+            };
+            let Some(target) = target else {
+                let message = match request.target {
+                    Some(target) => format!("No preview client {}", target),
+                    None => "No preview connected".to_string(),
+                };
+                return failed(clients, id, &request.msg_id, 0, &message);
+            };
+
+            let key = format!("{}:{}", id, request.msg_id);
+            PENDING.lock().unwrap().insert(
+                key.clone(),
+                Pending {
+                    requester: id,
+                    target,
+                    msg_id: request.msg_id,
+                    sent: Instant::now(),
+                },
+            );
+            expire(key.clone(), clients.clone());
+            let forwarded = Forwarded {
+                r#type: "eval::request",
+                body: &request.body,
+                msg_id: &key,
+                from: id,
+            };
+            send(clients, target, &forwarded)?;
+        }
+        "eval::result" => {
+            let reply = Reply::deserialize(&mut de)?;
+            let pending = {
+                let mut pending = PENDING.lock().unwrap();
+                // Only the preview that was asked may answer
+                match pending.get(&reply.msg_id) {
+                    Some(request) if request.target == id => pending.remove(&reply.msg_id),
+                    _ => None,
+                }
+            };
+            let Some(pending) = pending else {
+                error!("unexpected eval result from client {}", id);
+                return Ok(());
+            };
+            let result = EvalResult {
+                r#type: "eval::result",
+                body: "",
+                msg_id: &pending.msg_id,
+                client: id,
+                ok: reply.ok,
+                value: &reply.value,
+                error: &reply.error,
+            };
+            send(clients, pending.requester, &result)?;
+        }
+        other => error!("unknown msg type: {}", other),
+    }
+    Ok(())
+}
+
+/// Drops a disconnected client; requests it still owed an answer fail.
+pub fn forget(id: usize, clients: &Clients) {
+    let orphaned: Vec<Pending> = {
+        let mut pending = PENDING.lock().unwrap();
+        let keys: Vec<String> = pending
+            .iter()
+            .filter(|(_, request)| request.target == id || request.requester == id)
+            .map(|(key, _)| key.clone())
+            .collect();
+        keys.iter().filter_map(|key| pending.remove(key)).collect()
+    };
+    for request in orphaned.iter().filter(|request| request.target == id) {
+        let message = format!("Preview client {} disconnected", id);
+        if let Err(e) = failed(clients, request.requester, &request.msg_id, id, &message) {
+            error!("eval failure reply failed: {}", e);
+        }
+    }
+}
+
+/// Fails request `key` if it's still unanswered after `TIMEOUT`.
+fn expire(key: String, clients: Clients) {
+    actix_web::rt::spawn(async move {
+        time::sleep(TIMEOUT).await;
+        let expired = {
+            let mut pending = PENDING.lock().unwrap();
+            // The key may have been reused by a newer request since
+            match pending.get(&key) {
+                Some(request) if request.sent.elapsed() >= TIMEOUT => pending.remove(&key),
+                _ => None,
+            }
+        };
+        if let Some(request) = expired {
+            let message = format!("Preview client {} did not answer in time", request.target);
+            if let Err(e) = failed(
+                &clients,
+                request.requester,
+                &request.msg_id,
+                request.target,
+                &message,
+            ) {
+                error!("eval failure reply failed: {}", e);
+            }
+        }
+    });
+}
+
+fn failed(
+    clients: &Clients,
+    to: usize,
+    msg_id: &str,
+    client: usize,
+    message: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let error = json!({ "$type": "error", "name": "Error", "message": message, "stack": "" });
+    let result = EvalResult {
+        r#type: "eval::result",
+        body: "",
+        msg_id,
+        client,
+        ok: false,
+        value: &Value::Null,
+        error: &error,
+    };
+    send(clients, to, &result)
+}
+
+fn send<T: Serialize>(
+    clients: &Clients,
+    to: usize,
+    msg: &T,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = registry::encode(msg)?;
+    clients.lock().unwrap().send(to, bytes);
+    Ok(())
+}
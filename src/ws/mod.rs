@@ -1 +1,7 @@
 pub mod connection;
+pub mod console;
+pub mod eval;
+pub mod payload;
+pub mod pubsub;
+pub mod queue;
+pub mod registry;
@@ -0,0 +1,144 @@
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeMap, SerializeSeq, Serializer};
+
+/// Any msgpack value a client sends, kept as-is so it can be passed on:
+/// unlike `serde_json::Value`, `bin` stays binary and map keys needn't be
+/// strings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum Payload {
+    #[default]
+    Nil,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Bin(Vec<u8>),
+    Array(Vec<Payload>),
+    Map(Vec<(Payload, Payload)>),
+}
+
+impl Payload {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Payload::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn is_nil(&self) -> bool {
+        *self == Payload::Nil
+    }
+}
+
+impl From<String> for Payload {
+    fn from(s: String) -> Self {
+        Payload::Str(s)
+    }
+}
+
+impl Serialize for Payload {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Payload::Nil => serializer.serialize_unit(),
+            Payload::Bool(b) => serializer.serialize_bool(*b),
+            Payload::Int(i) => serializer.serialize_i64(*i),
+            Payload::UInt(u) => serializer.serialize_u64(*u),
+            Payload::Float(f) => serializer.serialize_f64(*f),
+            Payload::Str(s) => serializer.serialize_str(s),
+            Payload::Bin(b) => serializer.serialize_bytes(b),
+            Payload::Array(items) => {
+                let mut seq = serializer.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(item)?;
+                }
+                seq.end()
+            }
+            Payload::Map(entries) => {
+                let mut map = serializer.serialize_map(Some(entries.len()))?;
+                for (key, value) in entries {
+                    map.serialize_entry(key, value)?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Payload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(PayloadVisitor)
+    }
+}
+
+struct PayloadVisitor;
+
+impl<'de> Visitor<'de> for PayloadVisitor {
+    type Value = Payload;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a msgpack value")
+    }
+
+    fn visit_unit<E>(self) -> Result<Payload, E> {
+        Ok(Payload::Nil)
+    }
+
+    fn visit_none<E>(self) -> Result<Payload, E> {
+        Ok(Payload::Nil)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<Payload, D::Error> {
+        Payload::deserialize(deserializer)
+    }
+
+    fn visit_bool<E>(self, b: bool) -> Result<Payload, E> {
+        Ok(Payload::Bool(b))
+    }
+
+    fn visit_i64<E>(self, i: i64) -> Result<Payload, E> {
+        Ok(Payload::Int(i))
+    }
+
+    fn visit_u64<E>(self, u: u64) -> Result<Payload, E> {
+        Ok(Payload::UInt(u))
+    }
+
+    fn visit_f64<E>(self, f: f64) -> Result<Payload, E> {
+        Ok(Payload::Float(f))
+    }
+
+    fn visit_str<E: de::Error>(self, s: &str) -> Result<Payload, E> {
+        Ok(Payload::Str(s.to_string()))
+    }
+
+    fn visit_string<E>(self, s: String) -> Result<Payload, E> {
+        Ok(Payload::Str(s))
+    }
+
+    fn visit_bytes<E: de::Error>(self, b: &[u8]) -> Result<Payload, E> {
+        Ok(Payload::Bin(b.to_vec()))
+    }
+
+    fn visit_byte_buf<E>(self, b: Vec<u8>) -> Result<Payload, E> {
+        Ok(Payload::Bin(b))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Payload, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(1024));
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(Payload::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Payload, A::Error> {
+        let mut entries = Vec::with_capacity(map.size_hint().unwrap_or(0).min(1024));
+        while let Some(entry) = map.next_entry()? {
+            entries.push(entry);
+        }
+        Ok(Payload::Map(entries))
+    }
+}
@@ -0,0 +1,229 @@
+use std::collections::BTreeMap;
+use std::io::Cursor;
+use std::sync::Mutex;
+
+use log::error;
+use rmp_serde::Deserializer;
+use serde::{Deserialize, Serialize};
+
+use super::connection::Clients;
+use super::payload::Payload;
+use super::registry::{self, Filter};
+
+/// Topic pub/sub between clients. Topics are `/`-separated names; in a
+/// subscription `*` matches one segment and a trailing `**` any number.
+///
+/// - `subscribe` (`topic`: pattern) → acknowledged under the same type, then
+///   the retained messages it matches arrive with `retained: true`
+/// - `unsubscribe` (`topic`: the pattern as subscribed) → acknowledged
+/// - `publish` (`topic`, any msgpack `body`, `retain`) → every other
+///   subscriber gets `message` with `topic`, `body` and `from`; acknowledged
+///   with `delivered`. Retaining a nil body drops the retained message.
+pub fn handles(msg_type: &str) -> bool {
+    matches!(msg_type, "subscribe" | "unsubscribe" | "publish")
+}
+
+/// Whether subscription `pattern` covers `topic`.
+pub fn matches(pattern: &str, topic: &str) -> bool {
+    let mut topic = topic.split('/');
+    for segment in pattern.split('/') {
+        match segment {
+            "**" => return true,
+            "*" if topic.next().is_some_and(|segment| !segment.is_empty()) => {}
+            _ if topic.next() == Some(segment) => {}
+            _ => return false,
+        }
+    }
+    topic.next().is_none()
+}
+
+fn is_valid(topic: &str, pattern: bool) -> bool {
+    let segments: Vec<&str> = topic.split('/').collect();
+    segments
+        .iter()
+        .enumerate()
+        .all(|(i, segment)| match *segment {
+            "" => false,
+            "*" => pattern,
+            "**" => pattern && i == segments.len() - 1,
+            _ => !segment.contains('*'),
+        })
+}
+
+#[derive(Deserialize)]
+struct Request {
+    msg_id: String,
+    topic: String,
+    #[serde(default)]
+    body: Payload,
+    #[serde(default)]
+    retain: bool,
+}
+
+#[derive(Serialize)]
+struct Ack<'a> {
+    r#type: &'a str,
+    body: &'a str,
+    msg_id: &'a str,
+    topic: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    delivered: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Delivery<'a> {
+    r#type: &'a str,
+    body: &'a Payload,
+    msg_id: &'a str,
+    topic: &'a str,
+    /// Publishing client
+    from: usize,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    retained: bool,
+}
+
+struct Retained {
+    from: usize,
+    body: Payload,
+}
+
+lazy_static::lazy_static! {
+    /// Last message of each topic published with `retain`
+    static ref RETAINED: Mutex<BTreeMap<String, Retained>> = Mutex::new(BTreeMap::new());
+}
+
+/// Handles a pub/sub message from client `id`.
+pub fn handle(
+    id: usize,
+    msg_type: &str,
+    bin: &[u8],
+    clients: &Clients,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let request = Request::deserialize(&mut Deserializer::new(Cursor::new(bin)))?;
+    let mut ack = Ack {
+        r#type: msg_type,
+        body: "",
+        msg_id: &request.msg_id,
+        topic: &request.topic,
+        delivered: None,
+        error: None,
+    };
+    let pattern = msg_type != "publish";
+    if !is_valid(&request.topic, pattern) {
+        ack.error = Some(format!("Invalid topic {:?}", request.topic));
+        clients.lock().unwrap().send(id, registry::encode(&ack)?);
+        return Ok(());
+    }
+
+    match msg_type {
+        "subscribe" => {
+            let mut registry = clients.lock().unwrap();
+            let Some(client) = registry.get_mut(id) else {
+                return Ok(());
+            };
+            client.topics.insert(request.topic.clone());
+            registry.send(id, registry::encode(&ack)?);
+
+            let retained = RETAINED.lock().unwrap();
+            for (topic, message) in retained
+                .iter()
+                .filter(|(topic, _)| matches(&request.topic, topic))
+            {
+                let delivery = Delivery {
+                    r#type: "message",
+                    body: &message.body,
+                    msg_id: "",
+                    topic,
+                    from: message.from,
+                    retained: true,
+                };
+                registry.send(id, registry::encode(&delivery)?);
+            }
+        }
+        "unsubscribe" => {
+            let mut registry = clients.lock().unwrap();
+            if let Some(client) = registry.get_mut(id) {
+                client.topics.remove(&request.topic);
+            }
+            registry.send(id, registry::encode(&ack)?);
+        }
+        "publish" => {
+            let delivery = registry::encode(&Delivery {
+                r#type: "message",
+                body: &request.body,
+                msg_id: "",
+                topic: &request.topic,
+                from: id,
+                retained: false,
+            })?;
+            let audience = Filter {
+                topic: Some(request.topic.clone()),
+                except: Some(id),
+                ..Filter::default()
+            };
+            let registry = clients.lock().unwrap();
+            ack.delivered = Some(registry.broadcast(&audience, &delivery));
+            registry.send(id, registry::encode(&ack)?);
+            drop(registry);
+
+            if request.retain {
+                let mut retained = RETAINED.lock().unwrap();
+                if request.body.is_nil() {
+                    retained.remove(&request.topic);
+                } else {
+                    retained.insert(
+                        request.topic.clone(),
+                        Retained {
+                            from: id,
+                            body: request.body.clone(),
+                        },
+                    );
+                }
+            }
+        }
+        other => error!("unknown msg type: {}", other),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_segments_and_wildcards() {
+        assert!(matches("a/b", "a/b"));
+        assert!(!matches("a/b", "a/b/c"));
+        assert!(!matches("a/b/c", "a/b"));
+        assert!(matches("a/*/c", "a/b/c"));
+        assert!(!matches("a/*", "a/b/c"));
+        assert!(!matches("a/*", "a"));
+        // `*` stands for exactly one non-empty segment
+        assert!(!matches("a/*", "a/"));
+        assert!(!matches("*", ""));
+        // A trailing `**` also covers the topic it hangs off
+        assert!(matches("a/**", "a"));
+        assert!(matches("a/**", "a/b/c"));
+        assert!(!matches("a/**", "ab"));
+        assert!(matches("**", "a"));
+    }
+
+    #[test]
+    fn validates_topics_and_patterns() {
+        assert!(is_valid("a/b", false));
+        assert!(!is_valid("", false));
+        assert!(!is_valid("a//b", false));
+        assert!(!is_valid("a/", true));
+        assert!(!is_valid("a/*", false));
+        assert!(!is_valid("a/b*", true));
+        assert!(is_valid("a/*/c", true));
+        assert!(is_valid("a/**", true));
+        assert!(is_valid("**", true));
+        assert!(!is_valid("a/**", false));
+        // `**` only in the last position
+        assert!(!is_valid("a/**/c", true));
+        assert!(!is_valid("**/a", true));
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use actix_ws::Message;
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::config::{config, QueuePolicy};
+
+/// Outgoing messages of one client, bounded by `CLIENT_QUEUE_SIZE` so a
+/// stalled tab can't make the server buffer without limit.
+pub fn channel(client: usize) -> (Sender, Receiver) {
+    let settings = config();
+    bounded(
+        client,
+        settings.client_queue_size,
+        settings.client_queue_policy,
+    )
+}
+
+fn bounded(client: usize, capacity: usize, policy: QueuePolicy) -> (Sender, Receiver) {
+    let shared = Arc::new(Shared {
+        client,
+        capacity,
+        policy,
+        state: Mutex::new(State::default()),
+        notify: Notify::new(),
+    });
+    (
+        Sender {
+            shared: shared.clone(),
+        },
+        Receiver { shared },
+    )
+}
+
+/// Queue depth and what the policy had to do about it.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct QueueStats {
+    pub depth: usize,
+    /// Highest depth so far
+    pub peak: usize,
+    pub dropped: u64,
+    pub coalesced: u64,
+}
+
+#[derive(Debug)]
+pub enum SendError {
+    /// The connection is gone
+    Closed,
+    /// The queue was full and the client got disconnected for it
+    Full,
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SendError::Closed => f.write_str("connection closed"),
+            SendError::Full => f.write_str("send queue full"),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+struct Shared {
+    client: usize,
+    capacity: usize,
+    policy: QueuePolicy,
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+/// How a queued message may be let go of when the queue is full.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Kind {
+    /// Broadcast event, droppable; superseded by a newer one with the same
+    /// key under `QueuePolicy::Coalesce`
+    Event(Option<String>),
+    /// Answer to this client's own request, never dropped: the client
+    /// would wait for it forever
+    Reply,
+}
+
+#[derive(Default)]
+struct State {
+    items: VecDeque<(Message, Kind)>,
+    closed: bool,
+    /// Logged since the queue last drained
+    warned: bool,
+    stats: QueueStats,
+}
+
+#[derive(Clone)]
+pub struct Sender {
+    shared: Arc<Shared>,
+}
+
+impl Sender {
+    /// Queues an event, which the policy may drop if the client falls behind.
+    pub fn send(&self, msg: Message) -> Result<(), SendError> {
+        self.push(msg, Kind::Event(None))
+    }
+
+    /// Like `send`, but under `QueuePolicy::Coalesce` a queued message with
+    /// the same `key` is superseded by this one.
+    pub fn send_coalescing(&self, msg: Message, key: &str) -> Result<(), SendError> {
+        self.push(msg, Kind::Event(Some(key.to_string())))
+    }
+
+    /// Queues a reply to the client's own request. Replies are never
+    /// dropped; if only replies are left to make room, the client is
+    /// disconnected instead.
+    pub fn reply(&self, msg: Message) -> Result<(), SendError> {
+        self.push(msg, Kind::Reply)
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let state = self.shared.state.lock().unwrap();
+        QueueStats {
+            depth: state.items.len(),
+            ..state.stats
+        }
+    }
+
+    fn push(&self, msg: Message, kind: Kind) -> Result<(), SendError> {
+        let shared = &self.shared;
+        let mut state = shared.state.lock().unwrap();
+        if state.closed {
+            return Err(SendError::Closed);
+        }
+
+        let duplicate = match &kind {
+            Kind::Event(Some(_)) if shared.policy == QueuePolicy::Coalesce => {
+                state.items.iter().position(|(_, queued)| *queued == kind)
+            }
+            _ => None,
+        };
+        if let Some(index) = duplicate {
+            state.items.remove(index);
+            state.stats.coalesced += 1;
+        } else if state.items.len() >= shared.capacity {
+            if !state.warned {
+                state.warned = true;
+                println!(
+                    "[Queue] Client {} is {} messages behind ({:?})",
+                    shared.client,
+                    state.items.len(),
+                    shared.policy
+                );
+            }
+            let oldest_event = match shared.policy {
+                QueuePolicy::Disconnect => None,
+                _ => state
+                    .items
+                    .iter()
+                    .position(|(_, queued)| *queued != Kind::Reply),
+            };
+            let Some(index) = oldest_event else {
+                state.closed = true;
+                state.items.clear();
+                drop(state);
+                shared.notify.notify_one();
+                return Err(SendError::Full);
+            };
+            state.items.remove(index);
+            state.stats.dropped += 1;
+        }
+
+        state.items.push_back((msg, kind));
+        state.stats.peak = state.stats.peak.max(state.items.len());
+        drop(state);
+        shared.notify.notify_one();
+        Ok(())
+    }
+}
+
+pub struct Receiver {
+    shared: Arc<Shared>,
+}
+
+impl Receiver {
+    /// Next message; `None` once the client was disconnected for falling
+    /// behind.
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if let Some((msg, _)) = state.items.pop_front() {
+                    if state.items.is_empty() {
+                        state.warned = false;
+                    }
+                    return Some(msg);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            // `notify_one` keeps a permit, so a push in between isn't missed
+            self.shared.notify.notified().await;
+        }
+    }
+}
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.closed = true;
+        state.items.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(s: &str) -> Message {
+        Message::Text(s.to_string().into())
+    }
+
+    fn drain(rx: &Receiver) -> Vec<String> {
+        let mut state = rx.shared.state.lock().unwrap();
+        state
+            .items
+            .drain(..)
+            .map(|(msg, _)| match msg {
+                Message::Text(text) => text.to_string(),
+                other => format!("{:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn drop_oldest_keeps_replies() {
+        let (tx, rx) = bounded(1, 3, QueuePolicy::DropOldest);
+        tx.reply(text("reply")).unwrap();
+        tx.send(text("a")).unwrap();
+        tx.send(text("b")).unwrap();
+        tx.send(text("c")).unwrap();
+        let stats = tx.stats();
+        assert_eq!((stats.depth, stats.peak, stats.dropped), (3, 3, 1));
+        assert_eq!(drain(&rx), ["reply", "b", "c"]);
+    }
+
+    #[test]
+    fn drop_oldest_disconnects_when_only_replies_are_left() {
+        let (tx, rx) = bounded(1, 2, QueuePolicy::DropOldest);
+        tx.reply(text("one")).unwrap();
+        tx.reply(text("two")).unwrap();
+        assert!(matches!(tx.send(text("event")), Err(SendError::Full)));
+        assert!(matches!(tx.send(text("later")), Err(SendError::Closed)));
+        assert!(drain(&rx).is_empty());
+    }
+
+    #[test]
+    fn coalesce_supersedes_by_key() {
+        let (tx, rx) = bounded(1, 3, QueuePolicy::Coalesce);
+        tx.send_coalescing(text("x1"), "x").unwrap();
+        tx.send_coalescing(text("y1"), "y").unwrap();
+        tx.send_coalescing(text("x2"), "x").unwrap();
+        tx.send(text("plain")).unwrap();
+        // Full: the oldest event goes
+        tx.send(text("more")).unwrap();
+        let stats = tx.stats();
+        assert_eq!((stats.coalesced, stats.dropped, stats.peak), (1, 1, 3));
+        assert_eq!(drain(&rx), ["x2", "plain", "more"]);
+    }
+
+    #[test]
+    fn only_coalesce_merges_keys() {
+        let (tx, rx) = bounded(1, 3, QueuePolicy::DropOldest);
+        tx.send_coalescing(text("x1"), "x").unwrap();
+        tx.send_coalescing(text("x2"), "x").unwrap();
+        assert_eq!(tx.stats().coalesced, 0);
+        assert_eq!(drain(&rx), ["x1", "x2"]);
+    }
+
+    #[test]
+    fn disconnect_closes_when_full() {
+        let (tx, rx) = bounded(1, 2, QueuePolicy::Disconnect);
+        tx.send(text("a")).unwrap();
+        tx.send(text("b")).unwrap();
+        assert!(matches!(tx.send(text("c")), Err(SendError::Full)));
+        assert!(matches!(tx.reply(text("d")), Err(SendError::Closed)));
+        assert_eq!(tx.stats().dropped, 0);
+        assert_eq!(tx.stats().peak, 2);
+        assert!(drain(&rx).is_empty());
+    }
+
+    #[test]
+    fn dropped_receiver_closes() {
+        let (tx, rx) = bounded(1, 2, QueuePolicy::Coalesce);
+        drop(rx);
+        assert!(matches!(tx.send(text("a")), Err(SendError::Closed)));
+    }
+}
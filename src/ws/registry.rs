@@ -0,0 +1,469 @@
+use std::collections::{BTreeMap, HashSet};
+use std::io::Cursor;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use actix_ws::Message;
+use bytes::Bytes;
+use log::error;
+use rmp_serde::{Deserializer, Serializer};
+use serde::{Deserialize, Serialize};
+
+use super::connection::{Clients, Tx};
+use super::pubsub;
+use super::queue::QueueStats;
+use crate::http::security::Peer;
+
+/// Registry messages:
+///
+/// - `client::hello` (optional `role`, `workspace`, `url`) → updates the
+///   sender's entry and answers `client::hello` with `client` set to it. The
+///   same fields work as `/ws/` query parameters at connect time.
+/// - `client::list` (optional `roles`, `workspace`, `topic`) → `client::list`
+///   with the matching `clients`
+///
+/// IDE and tool clients are also sent `client::joined` and `client::left`
+/// (with `client`, and a `reason` for leaving) as others come and go.
+pub const PREFIX: &str = "client::";
+
+/// What is on the other end of a socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// An IDE window
+    Ide,
+    /// A page of the project, running the injected runtime
+    Preview,
+    /// Anything that isn't a browser (scripts, editor plugins)
+    Tool,
+}
+
+impl Role {
+    /// What a new socket counts as until it says otherwise.
+    pub fn default_for(peer: Peer, has_origin: bool) -> Role {
+        match peer {
+            Peer::Preview => Role::Preview,
+            Peer::Ide if has_origin => Role::Ide,
+            Peer::Ide => Role::Tool,
+        }
+    }
+
+    /// Previewed code can't pass itself off as the IDE.
+    pub fn allowed(self, peer: Peer) -> bool {
+        peer == Peer::Ide || self == Role::Preview
+    }
+}
+
+/// Self-description a client sends in `client::hello` or the `/ws/` query.
+#[derive(Debug, Default, Deserialize)]
+pub struct Hello {
+    #[serde(default)]
+    pub role: Option<Role>,
+    #[serde(default)]
+    pub workspace: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// One connected socket.
+pub struct Client {
+    pub tx: Tx,
+    pub role: Role,
+    /// Free-form grouping declared by the client, `""` if none
+    pub workspace: String,
+    /// `pubsub` subscription patterns
+    pub topics: HashSet<String>,
+    pub connected_at: SystemTime,
+    pub user_agent: String,
+    /// Page URL, for previews
+    pub url: String,
+}
+
+impl Client {
+    pub fn new(tx: Tx, role: Role, user_agent: String) -> Self {
+        Client {
+            tx,
+            role,
+            workspace: String::new(),
+            topics: HashSet::new(),
+            connected_at: SystemTime::now(),
+            user_agent,
+            url: String::new(),
+        }
+    }
+
+    /// Applies what `peer` is allowed of `hello`.
+    pub fn update(&mut self, hello: Hello, peer: Peer) {
+        match hello.role {
+            Some(role) if role.allowed(peer) => self.role = role,
+            Some(role) => error!("{:?} peer may not claim role {:?}", peer, role),
+            None => {}
+        }
+        if let Some(workspace) = hello.workspace {
+            self.workspace = workspace;
+        }
+        if let Some(url) = hello.url {
+            self.url = url;
+        }
+    }
+
+    fn info(&self, id: usize) -> ClientInfo<'_> {
+        let mut topics: Vec<&str> = self.topics.iter().map(String::as_str).collect();
+        topics.sort_unstable();
+        ClientInfo {
+            id,
+            role: self.role,
+            workspace: &self.workspace,
+            topics,
+            connected_at: self
+                .connected_at
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_millis() as u64),
+            user_agent: &self.user_agent,
+            url: &self.url,
+            queue: self.tx.stats(),
+        }
+    }
+}
+
+/// Which clients a message goes to; empty fields match everyone.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Filter {
+    #[serde(default)]
+    pub roles: Vec<Role>,
+    #[serde(default)]
+    pub workspace: Option<String>,
+    /// Clients with a subscription covering this topic
+    #[serde(default)]
+    pub topic: Option<String>,
+    /// Usually the sender; never taken from a message
+    #[serde(skip)]
+    pub except: Option<usize>,
+}
+
+impl Filter {
+    pub fn roles(roles: &[Role]) -> Self {
+        Filter {
+            roles: roles.to_vec(),
+            ..Filter::default()
+        }
+    }
+
+    pub fn matches(&self, id: usize, client: &Client) -> bool {
+        (self.roles.is_empty() || self.roles.contains(&client.role))
+            && self
+                .workspace
+                .as_ref()
+                .is_none_or(|workspace| *workspace == client.workspace)
+            && self.topic.as_ref().is_none_or(|topic| {
+                client
+                    .topics
+                    .iter()
+                    .any(|pattern| pubsub::matches(pattern, topic))
+            })
+            && self.except != Some(id)
+    }
+}
+
+/// `client::list` entry
+#[derive(Serialize)]
+pub struct ClientInfo<'a> {
+    pub id: usize,
+    pub role: Role,
+    pub workspace: &'a str,
+    pub topics: Vec<&'a str>,
+    /// Milliseconds since the Unix epoch
+    pub connected_at: u64,
+    pub user_agent: &'a str,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    pub url: &'a str,
+    /// Outgoing queue
+    pub queue: QueueStats,
+}
+
+/// Every connected socket by client id.
+#[derive(Default)]
+pub struct Registry {
+    clients: BTreeMap<usize, Client>,
+}
+
+impl Registry {
+    pub fn insert(&mut self, id: usize, client: Client) {
+        self.clients.insert(id, client);
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Client> {
+        self.clients.remove(&id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut Client> {
+        self.clients.get_mut(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.len()
+    }
+
+    /// Matching clients, oldest connection first.
+    pub fn query<'a>(
+        &'a self,
+        filter: &'a Filter,
+    ) -> impl Iterator<Item = (usize, &'a Client)> + 'a {
+        self.clients
+            .iter()
+            .filter(|(id, client)| filter.matches(**id, client))
+            .map(|(id, client)| (*id, client))
+    }
+
+    pub fn list(&self, filter: &Filter) -> Vec<ClientInfo<'_>> {
+        self.clients
+            .iter()
+            .filter(|(id, client)| filter.matches(**id, client))
+            .map(|(id, client)| client.info(*id))
+            .collect()
+    }
+
+    /// Queues a message meant for one client alone (a reply, as far as the
+    /// queue policy goes); `false` if it's gone.
+    pub fn send(&self, id: usize, bytes: Bytes) -> bool {
+        match self.clients.get(&id) {
+            Some(client) => client.tx.reply(Message::Binary(bytes)).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Queues a message for every matching client, returning how many got it.
+    pub fn broadcast(&self, filter: &Filter, bytes: &Bytes) -> usize {
+        self.broadcast_keyed(filter, bytes, None)
+    }
+
+    /// `broadcast`, superseding a message with the same `key` still queued
+    /// for a client.
+    pub fn broadcast_coalescing(&self, filter: &Filter, bytes: &Bytes, key: &str) -> usize {
+        self.broadcast_keyed(filter, bytes, Some(key))
+    }
+
+    fn broadcast_keyed(&self, filter: &Filter, bytes: &Bytes, key: Option<&str>) -> usize {
+        let mut sent = 0;
+        for (id, client) in self.query(filter) {
+            let msg = Message::Binary(bytes.clone());
+            let result = match key {
+                Some(key) => client.tx.send_coalescing(msg, key),
+                None => client.tx.send(msg),
+            };
+            match result {
+                Ok(()) => sent += 1,
+                Err(e) => error!("send to client {} failed: {}", id, e),
+            }
+        }
+        sent
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    msg_id: String,
+}
+
+#[derive(Serialize)]
+struct HelloReply<'a> {
+    r#type: &'a str,
+    body: &'a str,
+    msg_id: &'a str,
+    client: ClientInfo<'a>,
+}
+
+#[derive(Serialize)]
+struct ListReply<'a> {
+    r#type: &'a str,
+    body: &'a str,
+    msg_id: &'a str,
+    clients: Vec<ClientInfo<'a>>,
+}
+
+#[derive(Serialize)]
+struct Presence<'a> {
+    r#type: &'a str,
+    body: &'a str,
+    msg_id: &'a str,
+    client: ClientInfo<'a>,
+    #[serde(skip_serializing_if = "str::is_empty")]
+    reason: &'a str,
+}
+
+/// Tells the IDE and tool clients that `id` connected.
+pub fn joined(clients: &Clients, id: usize) {
+    let registry = clients.lock().unwrap();
+    if let Some(client) = registry.clients.get(&id) {
+        announce(&registry, "client::joined", id, client, "");
+    }
+}
+
+/// Unregisters `id` and tells the IDE and tool clients why it's gone.
+pub fn leave(clients: &Clients, id: usize, reason: &str) {
+    let mut registry = clients.lock().unwrap();
+    if let Some(client) = registry.remove(id) {
+        announce(&registry, "client::left", id, &client, reason);
+    }
+}
+
+fn announce(registry: &Registry, msg_type: &str, id: usize, client: &Client, reason: &str) {
+    let presence = Presence {
+        r#type: msg_type,
+        body: "",
+        msg_id: "",
+        client: client.info(id),
+        reason,
+    };
+    match encode(&presence) {
+        Ok(bytes) => {
+            let mut audience = Filter::roles(&[Role::Ide, Role::Tool]);
+            audience.except = Some(id);
+            registry.broadcast(&audience, &bytes);
+        }
+        Err(e) => error!("Serialize failed: {}", e),
+    }
+}
+
+/// Handles a `client::*` message from client `id`.
+pub fn handle(
+    id: usize,
+    peer: Peer,
+    msg_type: &str,
+    bin: &[u8],
+    clients: &Clients,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let request = Request::deserialize(&mut Deserializer::new(Cursor::new(bin)))?;
+    let mut registry = clients.lock().unwrap();
+    let bytes = match msg_type {
+        "client::hello" => {
+            let hello = Hello::deserialize(&mut Deserializer::new(Cursor::new(bin)))?;
+            let Some(client) = registry.get_mut(id) else {
+                return Ok(());
+            };
+            client.update(hello, peer);
+            encode(&HelloReply {
+                r#type: "client::hello",
+                body: "",
+                msg_id: &request.msg_id,
+                client: client.info(id),
+            })?
+        }
+        "client::list" => {
+            let filter = Filter::deserialize(&mut Deserializer::new(Cursor::new(bin)))?;
+            encode(&ListReply {
+                r#type: "client::list",
+                body: "",
+                msg_id: &request.msg_id,
+                clients: registry.list(&filter),
+            })?
+        }
+        other => {
+            error!("unknown msg type: {}", other);
+            return Ok(());
+        }
+    };
+    registry.send(id, bytes);
+    Ok(())
+}
+
+pub fn encode<T: Serialize>(msg: &T) -> Result<Bytes, rmp_serde::encode::Error> {
+    let mut buf = Vec::new();
+    msg.serialize(&mut Serializer::new(&mut buf).with_struct_map())?;
+    Ok(Bytes::from(buf))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::queue;
+
+    fn client(role: Role, workspace: &str, topics: &[&str]) -> Client {
+        let (tx, _rx) = queue::channel(0);
+        let mut client = Client::new(tx, role, String::new());
+        client.workspace = workspace.to_string();
+        client.topics = topics.iter().map(|t| t.to_string()).collect();
+        client
+    }
+
+    #[test]
+    fn empty_filter_matches_everyone() {
+        let filter = Filter::default();
+        assert!(filter.matches(1, &client(Role::Ide, "", &[])));
+        assert!(filter.matches(2, &client(Role::Preview, "w", &["a"])));
+    }
+
+    #[test]
+    fn filter_by_role_and_workspace() {
+        let ide = client(Role::Ide, "one", &[]);
+        let tool = client(Role::Tool, "two", &[]);
+
+        let filter = Filter::roles(&[Role::Ide, Role::Preview]);
+        assert!(filter.matches(1, &ide));
+        assert!(!filter.matches(2, &tool));
+
+        let filter = Filter {
+            workspace: Some("two".into()),
+            ..Filter::default()
+        };
+        assert!(!filter.matches(1, &ide));
+        assert!(filter.matches(2, &tool));
+
+        // Every field has to match
+        let filter = Filter {
+            workspace: Some("two".into()),
+            ..Filter::roles(&[Role::Ide])
+        };
+        assert!(!filter.matches(1, &ide));
+        assert!(!filter.matches(2, &tool));
+    }
+
+    #[test]
+    fn filter_by_topic_uses_subscription_patterns() {
+        let filter = Filter {
+            topic: Some("build/web/done".into()),
+            ..Filter::default()
+        };
+        assert!(filter.matches(1, &client(Role::Tool, "", &["build/**"])));
+        assert!(filter.matches(1, &client(Role::Tool, "", &["x", "build/*/done"])));
+        assert!(!filter.matches(1, &client(Role::Tool, "", &["build/*"])));
+        assert!(!filter.matches(1, &client(Role::Tool, "", &[])));
+    }
+
+    #[test]
+    fn filter_skips_the_excepted_client() {
+        let filter = Filter {
+            except: Some(1),
+            ..Filter::default()
+        };
+        let ide = client(Role::Ide, "", &[]);
+        assert!(!filter.matches(1, &ide));
+        assert!(filter.matches(2, &ide));
+    }
+
+    #[test]
+    fn roles_by_peer() {
+        assert_eq!(Role::default_for(Peer::Ide, true), Role::Ide);
+        assert_eq!(Role::default_for(Peer::Ide, false), Role::Tool);
+        assert_eq!(Role::default_for(Peer::Preview, true), Role::Preview);
+
+        assert!(Role::Ide.allowed(Peer::Ide));
+        assert!(Role::Preview.allowed(Peer::Preview));
+        assert!(!Role::Ide.allowed(Peer::Preview));
+        assert!(!Role::Tool.allowed(Peer::Preview));
+    }
+
+    #[test]
+    fn preview_hello_cannot_claim_ide() {
+        let mut preview = client(Role::Preview, "", &[]);
+        preview.update(
+            Hello {
+                role: Some(Role::Ide),
+                workspace: Some("w".into()),
+                url: None,
+            },
+            Peer::Preview,
+        );
+        assert_eq!(preview.role, Role::Preview);
+        assert_eq!(preview.workspace, "w");
+    }
+}
@@ -1,4 +1,5 @@
 import sh from "../sh.js";
+import { previewOrigin } from "../preview/origin.js";
 
 export class WssConsole extends HTMLElement {
   constructor() {
@@ -78,15 +79,78 @@ export class WssConsole extends HTMLElement {
     sh.console = this;
 
     globalThis.addEventListener("message", (e) => {
-      if (e.data.source === "iframe-console") {
-        this._logToUI(
-          `${e.data.type}-iframe`,
-          e.data.stack, // Source location from iframe
-          "[iframe]",
-          ...e.data.args,
-        );
+      // Only the preview may log here, not whatever else is framed
+      if (e.origin !== previewOrigin) return;
+      if (e.data.source === "iframe-console" && e.data.entry) {
+        const { entry } = e.data;
+        if (this._isDuplicate(entry.id)) return;
+        // Chained so remapped entries keep their original order
+        this._iframeQueue = this._iframeQueue
+          .then(() => this._remapStack(entry.stack))
+          .then((stack) => this._logEntry(entry, stack, "[iframe]"));
       }
     });
+
+    // Every preview (other tabs and devices too), relayed by the server with
+    // stacks already remapped
+    sh.event.on("console::entry", (msg) => {
+      if (this._isDuplicate(msg.entry.id)) return;
+      const label = msg.history ? "[history]" : `[preview ${msg.client}]`;
+      this._iframeQueue = this._iframeQueue.then(() =>
+        this._logEntry(msg.entry, msg.entry.stack, label),
+      );
+    });
+  }
+
+  /** @type {Promise<void>} */
+  _iframeQueue = Promise.resolve();
+
+  /** Ids of preview entries shown, as the framed preview sends each twice */
+  _seen = new Set();
+
+  _isDuplicate(id) {
+    if (this._seen.has(id)) return true;
+    this._seen.add(id);
+    if (this._seen.size > 1000) {
+      this._seen.delete(this._seen.values().next().value);
+    }
+    return false;
+  }
+
+  /**
+   * Renders a preview console entry (see inject_scripts/console-override.js).
+   * @param {{level: string, args: any[]}} entry
+   * @param {string} stack
+   * @param {string} label
+   */
+  _logEntry(entry, stack, label) {
+    const levels = { exception: "error", rejection: "error", debug: "log" };
+    const type = levels[entry.level] ?? entry.level;
+    const args = entry.args.map(revive);
+    if (entry.level === "exception") args.unshift("Uncaught");
+    if (entry.level === "rejection") args.unshift("Uncaught (in promise)");
+    if (entry.level === "clear") args.push("console.clear()");
+    // Errors carry a full stack, the call site is its first frame
+    const site = stack.split("\n").find((line) => /:\d+:\d+\)?$/.test(line.trim()));
+    this._logToUI(type, site?.trim() ?? "", label, ...args);
+  }
+
+  /**
+   * Maps transpiled `/project/*.ts(x)` locations back to the original source.
+   * @param {string} stack
+   * @returns {Promise<string>}
+   */
+  async _remapStack(stack) {
+    if (!stack || !sh.ws) return stack;
+    try {
+      const result = await sh.ws.send(
+        { type: "stack::remap", body: stack },
+        { echo: false },
+      );
+      return result.body;
+    } catch {
+      return stack;
+    }
   }
 
   disconnectedCallback() {
@@ -167,4 +231,52 @@ export class WssConsole extends HTMLElement {
   }
 }
 
+/**
+ * Turns the `{ $type }` placeholders of serialized preview values back into
+ * something `_logToUI` renders the usual way.
+ */
+function revive(value) {
+  if (Array.isArray(value)) return value.map(revive);
+  if (value === null || typeof value !== "object") return value;
+  switch (value.$type) {
+    case undefined: {
+      const out = {};
+      for (const [key, item] of Object.entries(value)) out[key] = revive(item);
+      return out;
+    }
+    case "undefined":
+      return undefined;
+    case "number":
+      return Number(value.value);
+    case "bigint":
+      return `${value.value}n`;
+    case "function":
+      return Object.defineProperty(function () {}, "name", { value: value.name });
+    case "error": {
+      const error = new Error(value.message);
+      error.name = value.name;
+      error.stack = value.stack || `${value.name}: ${value.message}`;
+      return error;
+    }
+    case "date":
+      return new Date(value.value);
+    case "map":
+      return new Map(value.entries.map(([k, v]) => [revive(k), revive(v)]));
+    case "set":
+      return new Set(value.values.map(revive));
+    case "symbol":
+    case "regexp":
+    case "node":
+      return value.value;
+    case "binary":
+      return `${value.name}(${value.length})`;
+    case "more":
+      return `… ${value.count} more`;
+    case "circular":
+      return "[Circular]";
+    default:
+      return `[${value.name ?? value.$type}]`;
+  }
+}
+
 globalThis.customElements.define("wss-console", WssConsole);
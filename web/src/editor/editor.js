@@ -8,6 +8,8 @@ import {
   vim,
   Vim,
   getCM,
+  lintGutter,
+  setDiagnostics,
 } from "./pme/pme.mod.js";
 import { customKeymap } from "./keymaps.js";
 import { gen_hash } from "/src/lib.js"; // This might not be needed if id is from tab-id
@@ -69,6 +71,7 @@ export class WssEditor extends HTMLElement {
         this.theme.of(oneDark),
         this.language.of([]),
         autocompletion(),
+        lintGutter(),
         EditorView.lineWrapping,
         // code_lens(),
         EditorView.updateListener.of((update) => {
@@ -100,6 +103,7 @@ export class WssEditor extends HTMLElement {
     });
 
     sh.event.on("editor::update", this.update_handler);
+    sh.event.on("editor::diagnostics", this.diagnostics_handler);
   }
 
   disconnectedCallback() {
@@ -108,6 +112,7 @@ export class WssEditor extends HTMLElement {
     this.removeEventListener("save", this.save);
     this.removeEventListener("save-as", this.saveAs);
     sh.event.off("editor::update", this.update_handler);
+    sh.event.off("editor::diagnostics", this.diagnostics_handler);
   }
 
   update_handler = (path) => {
@@ -118,6 +123,32 @@ export class WssEditor extends HTMLElement {
     }
   };
 
+  /**
+   * Underlines transpile errors reported by the server for this file.
+   * @param {{type: string, body: string, diagnostics: Array<object>}} msg
+   */
+  diagnostics_handler = (msg) => {
+    if (this.full_path !== msg.body) return;
+
+    const doc = this.view.state.doc;
+    const offset = (line, column) => {
+      const l = doc.line(Math.min(Math.max(line, 1), doc.lines));
+      return Math.min(l.from + column - 1, l.to);
+    };
+
+    const diagnostics = msg.diagnostics.map((d) => {
+      const from = offset(d.line, d.column);
+      return {
+        from,
+        to: Math.max(from, offset(d.end_line, d.end_column)),
+        severity: "error",
+        message: d.message,
+      };
+    });
+
+    this.view.dispatch(setDiagnostics(this.view.state, diagnostics));
+  };
+
   async load(path) {
     try {
       const result = await sh.ws.send({
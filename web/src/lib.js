@@ -1,4 +1,4 @@
-export * from "https://cdn.jsdelivr.net/npm/@msgpack/msgpack@3.1.3/dist.esm/index.mjs";
+export * from "/__wss/vendor/msgpack.js";
 
 /// gens
 /**
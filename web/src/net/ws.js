@@ -28,6 +28,10 @@ const ws = {
     this.instance.onopen = () => {
       terminalInstance.println("WebSocket connected.");
       this.ready.resolve();
+      // Subscriptions don't survive the server side of a reconnect
+      for (const topic of this.subscriptions.keys()) {
+        this.send({ type: "subscribe", body: "", topic }, { echo: false });
+      }
     };
 
     this.instance.onmessage = async (event) => {
@@ -99,6 +103,33 @@ const ws = {
             document.dispatchEvent(
               new CustomEvent("wss-js-update", { detail: unpacked.body }),
             );
+          } else if (unpacked.type === "diagnostics::error") {
+            for (const d of unpacked.diagnostics) {
+              terminalInstance.println(
+                `ERROR: ${d.file}:${d.line}:${d.column} - ${d.message}`,
+                "red",
+              );
+            }
+            sh.event.emit("editor::diagnostics", unpacked);
+          } else if (unpacked.type === "diagnostics::clear") {
+            sh.event.emit("editor::diagnostics", unpacked);
+          } else if (unpacked.type === "stack::remap_result") {
+            // resolved through `pending` above
+          } else if (unpacked.type === "client::joined" || unpacked.type === "client::left") {
+            sh.event.emit(unpacked.type, unpacked);
+            const { id, role } = unpacked.client;
+            const reason = unpacked.reason ? ` (${unpacked.reason})` : "";
+            const verb = unpacked.type === "client::joined" ? "joined" : "left";
+            terminalInstance.println(`CLIENT: #${id} ${role} ${verb}${reason}`, "gray");
+          } else if (/^(eval|client)::|^(un)?subscribe$|^publish$/.test(unpacked.type)) {
+            // resolved through `pending` above
+          } else if (unpacked.type === "message") {
+            for (const [pattern, handlers] of this.subscriptions) {
+              if (!topicMatches(pattern, unpacked.topic)) continue;
+              for (const handler of handlers) handler(unpacked.body, unpacked);
+            }
+          } else if (unpacked.type.startsWith("console::")) {
+            sh.event.emit("console::entry", unpacked);
           } else if (unpacked.type === "notify::update") {
             terminalInstance.println(
               `NOTIFY: update - ${unpacked.body}`,
@@ -154,10 +185,11 @@ const ws = {
   /**
    * Sends a message over the WebSocket connection.
    * @param {object} message - The message object to send.
+   * @param {{echo?: boolean}} [options] - `echo: false` keeps the message out of the terminal.
    * @returns {Promise<any>} A promise that resolves with the server's response.
    */
-  send: async function (message) {
-    this.terminalInstance.println("> " + message.body);
+  send: async function (message, { echo = true } = {}) {
+    if (echo) this.terminalInstance.println("> " + message.body);
     await this.ready.promise;
     message.msg_id = gen_hash();
 
@@ -168,6 +200,59 @@ const ws = {
 
     return pending.promise;
   },
+
+  /** @type {Map<string, Set<(body: any, message: object) => void>>} */
+  subscriptions: new Map(),
+
+  /**
+   * Calls `handler` for messages published to topics matching `pattern`
+   * (`*`: one `/`-separated segment, trailing `**`: any number).
+   * @param {string} pattern
+   * @param {(body: any, message: object) => void} handler
+   * @returns {() => void} Unsubscribes the handler.
+   */
+  subscribe: function (pattern, handler) {
+    let handlers = this.subscriptions.get(pattern);
+    if (!handlers) {
+      handlers = new Set();
+      this.subscriptions.set(pattern, handlers);
+      this.send({ type: "subscribe", body: "", topic: pattern }, { echo: false });
+    }
+    handlers.add(handler);
+    return () => {
+      handlers.delete(handler);
+      if (handlers.size || this.subscriptions.get(pattern) !== handlers) return;
+      this.subscriptions.delete(pattern);
+      this.send({ type: "unsubscribe", body: "", topic: pattern }, { echo: false });
+    };
+  },
+
+  /**
+   * Sends `body` (any msgpack-encodable value) to the topic's subscribers.
+   * @param {string} topic
+   * @param {any} body
+   * @param {{retain?: boolean}} [options] - `retain`: kept for later subscribers.
+   * @returns {Promise<any>} The acknowledgement, with `delivered` or `error`.
+   */
+  publish: function (topic, body, { retain = false } = {}) {
+    return this.send({ type: "publish", body, topic, retain }, { echo: false });
+  },
 };
 
+/**
+ * Whether subscription `pattern` covers `topic` (mirrors `pubsub::matches`).
+ * @param {string} pattern
+ * @param {string} topic
+ * @returns {boolean}
+ */
+function topicMatches(pattern, topic) {
+  const want = pattern.split("/");
+  const have = topic.split("/");
+  for (let i = 0; i < want.length; i++) {
+    if (want[i] === "**") return true;
+    if (i >= have.length || (want[i] !== "*" && want[i] !== have[i])) return false;
+  }
+  return want.length === have.length;
+}
+
 sh.ws = ws;
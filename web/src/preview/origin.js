@@ -0,0 +1,8 @@
+/**
+ * Origin the project preview is served from. The server sets the meta tag
+ * when `PREVIEW_ORIGIN` isolates the preview; otherwise it shares ours.
+ * @type {string}
+ */
+export const previewOrigin =
+  document.querySelector('meta[name="wss-preview-origin"]')?.content ||
+  location.origin;
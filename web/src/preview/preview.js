@@ -1,5 +1,6 @@
 import sh from "../sh.js";
 import { decodeMulti } from "../lib.js";
+import { previewOrigin } from "./origin.js";
 
 /**
  * A custom element for the preview panel, which displays a live preview of the project.
@@ -89,7 +90,7 @@ export class WssPreviewPanel extends HTMLElement {
         <span id="preview-dimensions"></span>
       </div>
       <div class="resizable-iframe-container">
-        <iframe src="/project/" id="preview-iframe"></iframe>
+        <iframe src="${previewOrigin}/project/" id="preview-iframe" allow="cross-origin-isolated"></iframe>
         <div class="iframe-resizer-bottom"></div>
       </div>
     `;
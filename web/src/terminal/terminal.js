@@ -140,7 +140,7 @@ export class WssTerminal extends HTMLElement {
     this.inputElement.focus();
     const url = `${
       window.location.protocol === "https:" ? "wss:" : "ws:"
-    }//${window.location.host}/ws/`;
+    }//${window.location.host}/ws/?role=ide`;
     sh.ws.connect(url, this);
   }
 
@@ -211,16 +211,60 @@ export class WssTerminal extends HTMLElement {
     this.inputElement.style.height = this.inputElement.scrollHeight + "px";
   }
 
+  /**
+   * `js [@<client>] <expr>`: evaluates in a preview page, or lists them.
+   * @param {string} args
+   */
+  async evaluate(args) {
+    if (!args) {
+      const { targets } = await sh.ws.send({ type: "eval::targets", body: "" }, { echo: false });
+      if (!targets.length) this.println("No preview connected.", "orange");
+      for (const { client, url } of targets) this.println(`  @${client}  ${url}`);
+      return;
+    }
+    const [, target, expression] = args.match(/^(?:@(\d+)\s+)?([\s\S]*)$/);
+    const message = { type: "eval::request", body: expression };
+    if (target) message.target = Number(target);
+    this.println(`js> ${expression}`);
+    const result = await sh.ws.send(message, { echo: false });
+    if (result.ok) {
+      this.println(formatValue(result.value));
+    } else {
+      this.println(formatValue(result.error), "red");
+    }
+  }
+
+  /** `clients`: every socket connected to the server. */
+  async listClients() {
+    const { clients } = await sh.ws.send({ type: "client::list", body: "" }, { echo: false });
+    for (const client of clients) {
+      const since = new Date(client.connected_at).toLocaleTimeString();
+      const where = client.url || client.user_agent;
+      const workspace = client.workspace ? ` [${client.workspace}]` : "";
+      const { depth, dropped } = client.queue;
+      const queue = depth || dropped ? `  queued ${depth}, dropped ${dropped}` : "";
+      this.println(`  #${client.id}  ${client.role}${workspace}  since ${since}${queue}  ${where}`);
+    }
+  }
+
   /**
    * Executes a command.
    * @param {string} command - The command to execute.
    */
   executeCommand(command) {
+    if (/^js(\s|$)/.test(command)) {
+      this.evaluate(command.slice(2).trim());
+      return;
+    }
     switch (command.toLowerCase()) {
       case "help":
         this.println("Available commands:");
         this.println("  help         - Show this help message");
         this.println("  clear        - Clear the terminal output");
+        this.println("  js <expr>    - Evaluate in the latest preview page");
+        this.println("  js @<id> <expr> - Evaluate in preview client <id>");
+        this.println("  js           - List preview clients");
+        this.println("  clients      - List connected clients");
         this.println(
           "  Any other command will be sent to the server via WebSocket.",
         );
@@ -228,6 +272,9 @@ export class WssTerminal extends HTMLElement {
       case "clear":
         this.outputElement.innerHTML = "";
         break;
+      case "clients":
+        this.listClients();
+        break;
       default:
         sh.ws.send({
           type: "cmd",
@@ -238,4 +285,20 @@ export class WssTerminal extends HTMLElement {
   }
 }
 
+/**
+ * Terminal rendering of a value serialized by the preview runtime.
+ * @param {any} value
+ * @returns {string}
+ */
+function formatValue(value) {
+  if (typeof value === "string") return JSON.stringify(value);
+  if (value?.$type === "undefined") return "undefined";
+  if (value?.$type === "error") return value.stack || `${value.name}: ${value.message}`;
+  if (value?.$type === "function") return `ƒ ${value.name}()`;
+  if (["number", "bigint", "symbol", "regexp", "node"].includes(value?.$type)) {
+    return value.$type === "bigint" ? `${value.value}n` : String(value.value);
+  }
+  return JSON.stringify(value, null, 2);
+}
+
 customElements.define("wss-terminal", WssTerminal);